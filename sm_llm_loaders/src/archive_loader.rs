@@ -0,0 +1,481 @@
+use std::io::{Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+
+use crate::docx_loader::parse_docx;
+use crate::epub_loader::parse_epub;
+use crate::incremental::file_revision;
+use crate::loader_registry::TEXT_FILE_EXTENSIONS;
+use crate::pdf_loader::parse_pdf;
+use crate::text_file_loader::parse_text;
+use crate::{
+    stamp_document, Document, DocumentLoader, LoaderError, SourceMetadata, TabularFormat,
+    TabularLoader,
+};
+
+/// Archives nested deeper than this are skipped.
+const MAX_ARCHIVE_DEPTH: usize = 4;
+
+/// Default bytes the entries of an archive may unpack to, nested archives
+/// included.
+const DEFAULT_MAX_UNPACKED_BYTES: u64 = 1 << 30;
+
+const ARCHIVE_EXTENSIONS: &[&str] = &[".zip", ".tar", ".tgz", ".tzst"];
+
+const COMPRESSED_EXTENSIONS: &[&str] = &[".gz", ".zst"];
+
+/// Whether `path` is an archive or compressed file `ArchiveLoader` opens.
+/// Compressed files are only claimed when the file inside is supported
+/// (`notes.txt.gz`, but not `backup.sql.gz`).
+pub fn is_archive_path(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_lowercase();
+    if ARCHIVE_EXTENSIONS.iter().any(|ext| name.ends_with(ext)) {
+        return true;
+    }
+
+    COMPRESSED_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+        && path
+            .file_stem()
+            .map(Path::new)
+            .is_some_and(|inner| is_archive_path(inner) || is_document_path(inner))
+}
+
+/// Whether `path` is a single document format `parse_reader` handles.
+fn is_document_path(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    ["pdf", "epub", "docx"].contains(&extension.as_str())
+        || TEXT_FILE_EXTENSIONS.contains(&extension.as_str())
+        || TabularFormat::from_path(path).is_some()
+}
+
+/// Loads compressed files and archives.
+///
+/// `.gz` and `.zst` files are decompressed transparently and handed to
+/// the loader of the inner file (`notes.txt.gz` loads as a text file).
+/// `.zip` and `.tar` archives (also `.tar.gz`/`.tgz` and `.tar.zst`) are
+/// walked and every supported entry is loaded, including nested archives.
+/// Archives are streamed from the file, one entry at a time, except
+/// nested ZIP archives which are read into memory first.
+///
+/// Every document records the `archive_path` it came from and, for
+/// archive entries, its `inner_path` inside the archive. Entries nested
+/// in inner archives get `outer.zip/inner.txt` style inner paths.
+/// Unsupported entries are skipped, an archive without any supported
+/// entry loads no documents. Entries that fail to load are logged and
+/// skipped. Loading fails once the entries read unpack to more than
+/// `max_unpacked_bytes`.
+pub struct ArchiveLoader {
+    pub path: PathBuf,
+    pub max_unpacked_bytes: u64,
+}
+
+impl ArchiveLoader {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            max_unpacked_bytes: DEFAULT_MAX_UNPACKED_BYTES,
+        }
+    }
+
+    pub fn with_max_unpacked_bytes(mut self, max_unpacked_bytes: u64) -> Self {
+        self.max_unpacked_bytes = max_unpacked_bytes;
+        self
+    }
+}
+
+#[async_trait]
+impl DocumentLoader<SourceMetadata> for ArchiveLoader {
+    async fn load(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let revision = file_revision(&self.path).await?;
+        let path = self.path.clone();
+        let mut budget = Budget::new(self.max_unpacked_bytes);
+
+        let mut docs = tokio::task::spawn_blocking(move || {
+            let name = path.to_string_lossy().to_string();
+            let mut file = std::fs::File::open(&path)?;
+            if name.to_lowercase().ends_with(".zip") {
+                parse_zip(file, &mut budget, 0).map(Some)
+            } else {
+                parse_reader(&name, &mut file, &mut budget, 0)
+            }
+        })
+        .await
+        .map_err(|e| LoaderError::SourceReadError(e.to_string()))??
+        .unwrap_or_default();
+
+        let archive_path = self.path.to_string_lossy().to_string();
+        for doc in docs.iter_mut() {
+            doc.metadata
+                .get_or_insert_with(Vec::new)
+                .push(("archive_path".to_string(), archive_path.clone()));
+            stamp_document(doc, Some(&revision));
+        }
+
+        Ok(docs)
+    }
+
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        file_revision(&self.path).await.map(Some)
+    }
+}
+
+/// Bytes an archive may still unpack.
+struct Budget {
+    max: u64,
+    remaining: u64,
+    exceeded: bool,
+}
+
+impl Budget {
+    fn new(max: u64) -> Self {
+        Self {
+            max,
+            remaining: max,
+            exceeded: false,
+        }
+    }
+
+    /// Reads the rest of `reader`, failing once over budget.
+    fn read(&mut self, reader: &mut dyn Read) -> Result<Vec<u8>, LoaderError> {
+        let mut bytes = vec![];
+        Read::take(&mut *reader, self.remaining.saturating_add(1)).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 > self.remaining {
+            self.exceeded = true;
+            return Err(LoaderError::SourceReadError(format!(
+                "Archive unpacks to more than {} bytes",
+                self.max
+            )));
+        }
+        self.remaining -= bytes.len() as u64;
+
+        Ok(bytes)
+    }
+}
+
+/// Parses a file streamed from `reader`, picking the format from `name`.
+/// Returns `None` for unsupported formats.
+fn parse_reader(
+    name: &str,
+    reader: &mut dyn Read,
+    budget: &mut Budget,
+    depth: usize,
+) -> Result<Option<Vec<Document<SourceMetadata>>>, LoaderError> {
+    let lower_name = name.to_lowercase();
+    if !is_archive_path(Path::new(&lower_name)) && !is_document_path(Path::new(&lower_name)) {
+        return Ok(None);
+    }
+
+    if let Some(stem) = strip_suffix(name, &lower_name, ".tgz") {
+        let mut decoder = flate2::read::MultiGzDecoder::new(reader);
+        return parse_reader(&format!("{stem}.tar"), &mut decoder, budget, depth);
+    }
+    if let Some(stem) = strip_suffix(name, &lower_name, ".tzst") {
+        let mut decoder = zstd::stream::read::Decoder::new(reader)?;
+        return parse_reader(&format!("{stem}.tar"), &mut decoder, budget, depth);
+    }
+    if let Some(stem) = strip_suffix(name, &lower_name, ".gz") {
+        let mut decoder = flate2::read::MultiGzDecoder::new(reader);
+        return parse_reader(stem, &mut decoder, budget, depth);
+    }
+    if let Some(stem) = strip_suffix(name, &lower_name, ".zst") {
+        let mut decoder = zstd::stream::read::Decoder::new(reader)?;
+        return parse_reader(stem, &mut decoder, budget, depth);
+    }
+
+    if lower_name.ends_with(".zip") || lower_name.ends_with(".tar") {
+        if depth >= MAX_ARCHIVE_DEPTH {
+            return Ok(None);
+        }
+        let docs = if lower_name.ends_with(".zip") {
+            // ZIP entries are found from the end of the archive
+            let bytes = budget.read(reader)?;
+            parse_zip(Cursor::new(bytes), budget, depth)?
+        } else {
+            parse_tar(reader, budget, depth)?
+        };
+
+        return Ok(Some(docs));
+    }
+
+    let bytes = budget.read(reader)?;
+    let extension = Path::new(&lower_name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let docs = match extension.as_str() {
+        "pdf" => parse_pdf(&bytes, name)?,
+        "epub" => parse_epub(bytes, name)?,
+        "docx" => parse_docx(bytes, name)?,
+        ext if TEXT_FILE_EXTENSIONS.contains(&ext) => parse_text(bytes, name)?,
+        _ => match TabularFormat::from_path(Path::new(&lower_name)) {
+            Some(format) => TabularLoader::parse_bytes(&bytes, format, name)?,
+            None => return Ok(None),
+        },
+    };
+
+    Ok(Some(docs))
+}
+
+fn parse_zip<R: Read + Seek>(
+    reader: R,
+    budget: &mut Budget,
+    depth: usize,
+) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+    let mut archive = zip::ZipArchive::new(reader)
+        .map_err(|e| LoaderError::SourceReadError(format!("Invalid ZIP archive: {e}")))?;
+
+    let mut docs = vec![];
+    for index in 0..archive.len() {
+        let mut entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("Skipping invalid ZIP entry {index}: {e}");
+                continue;
+            }
+        };
+        if entry.is_dir() {
+            continue;
+        }
+
+        let inner_path = entry.name().to_string();
+        parse_entry(&inner_path, &mut entry, budget, depth, &mut docs)?;
+    }
+
+    Ok(docs)
+}
+
+fn parse_tar(
+    reader: &mut dyn Read,
+    budget: &mut Budget,
+    depth: usize,
+) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+    let mut archive = tar::Archive::new(reader);
+
+    let mut docs = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let inner_path = entry.path()?.to_string_lossy().to_string();
+        parse_entry(&inner_path, &mut entry, budget, depth, &mut docs)?;
+    }
+
+    Ok(docs)
+}
+
+/// Parses an archive entry into `docs`. An entry that fails to load is
+/// logged and skipped, unless the archive is over budget.
+fn parse_entry(
+    inner_path: &str,
+    reader: &mut dyn Read,
+    budget: &mut Budget,
+    depth: usize,
+    docs: &mut Vec<Document<SourceMetadata>>,
+) -> Result<(), LoaderError> {
+    match parse_reader(inner_path, reader, budget, depth + 1) {
+        Ok(entry_docs) => docs.extend(
+            entry_docs
+                .into_iter()
+                .flatten()
+                .map(|d| with_inner_path(d, inner_path)),
+        ),
+        Err(e) if budget.exceeded => return Err(e),
+        Err(e) => eprintln!("Skipping archive entry {inner_path}: {e}"),
+    }
+
+    Ok(())
+}
+
+fn strip_suffix<'a>(name: &'a str, lower_name: &str, suffix: &str) -> Option<&'a str> {
+    lower_name
+        .ends_with(suffix)
+        .then(|| &name[..name.len() - suffix.len()])
+}
+
+/// Records `inner_path`, prefixing the inner path a nested archive
+/// already set.
+fn with_inner_path(
+    mut doc: Document<SourceMetadata>,
+    inner_path: &str,
+) -> Document<SourceMetadata> {
+    let metadata = doc.metadata.get_or_insert_with(Vec::new);
+
+    match metadata.iter_mut().find(|(k, _)| k == "inner_path") {
+        Some((_, nested)) => *nested = format!("{inner_path}/{nested}"),
+        None => metadata.push(("inner_path".to_string(), inner_path.to_string())),
+    }
+
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    fn zip_bytes(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in entries {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn tar_bytes(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (name, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn metadata_value<'a>(doc: &'a Document<SourceMetadata>, key: &str) -> Option<&'a str> {
+        doc.metadata
+            .as_ref()?
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn is_archive_path_test() {
+        for path in [
+            "a.zip",
+            "a.TAR",
+            "a.tgz",
+            "a.tar.zst",
+            "notes.txt.gz",
+            "rows.csv.zst",
+        ] {
+            assert!(is_archive_path(Path::new(path)), "{path}");
+        }
+        for path in ["backup.sql.gz", "photo.png.zst", "data.gz", "notes.txt"] {
+            assert!(!is_archive_path(Path::new(path)), "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn load_test() {
+        let dir =
+            std::env::temp_dir().join(format!("sm_llm_loaders_archive_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let inner_zip = zip_bytes(&[("b.md", b"Nested")]);
+        let files: Vec<(&str, Vec<u8>)> = vec![
+            (
+                "bundle.zip",
+                zip_bytes(&[
+                    ("docs/a.txt", b"Hello"),
+                    ("image.png", b"\x89PNG"),
+                    ("inner.zip", &inner_zip),
+                ]),
+            ),
+            (
+                "bundle.tar.gz",
+                gzip(&tar_bytes(&[("notes/c.txt", b"Tarred")])),
+            ),
+            ("notes.txt.gz", gzip(b"Compressed")),
+            ("images.zip", zip_bytes(&[("image.png", b"\x89PNG")])),
+        ];
+        for (name, bytes) in files.iter() {
+            std::fs::write(dir.join(name), bytes).unwrap();
+        }
+
+        let mut loaded = vec![];
+        for (name, _) in files.iter() {
+            let path = dir.join(name);
+            let docs = ArchiveLoader::new(&path).load().await.unwrap();
+            for doc in docs.iter() {
+                assert_eq!(
+                    metadata_value(doc, "archive_path"),
+                    Some(path.to_string_lossy().as_ref())
+                );
+                assert!(metadata_value(doc, "source_revision").is_some());
+            }
+            loaded.push(
+                docs.iter()
+                    .map(|d| {
+                        (
+                            d.page_content.clone(),
+                            metadata_value(d, "inner_path").map(String::from),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            loaded,
+            vec![
+                vec![
+                    ("Hello".to_string(), Some("docs/a.txt".to_string())),
+                    ("Nested".to_string(), Some("inner.zip/b.md".to_string())),
+                ],
+                vec![("Tarred".to_string(), Some("notes/c.txt".to_string()))],
+                vec![("Compressed".to_string(), None)],
+                vec![],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn load_failing_entries_test() {
+        let dir = std::env::temp_dir().join(format!(
+            "sm_llm_loaders_archive_failing_{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("bundle.tar");
+        std::fs::write(
+            &path,
+            tar_bytes(&[
+                ("a.txt", b"Hello"),
+                ("broken.zip", b"not a zip"),
+                ("broken.docx", b"not a docx"),
+                ("b.txt", b"World"),
+            ]),
+        )
+        .unwrap();
+
+        let docs = ArchiveLoader::new(&path).load().await.unwrap();
+        let contents: Vec<&str> = docs.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(contents, vec!["Hello", "World"]);
+
+        // 5 bytes of each text entry and 9 of the invalid ZIP
+        let capped = ArchiveLoader::new(&path).with_max_unpacked_bytes(16);
+        assert!(capped.load().await.is_err());
+        let fitting = ArchiveLoader::new(&path).with_max_unpacked_bytes(30);
+        assert_eq!(fitting.load().await.unwrap().len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+use regex::Regex;
+
+use crate::{SourceMetadata, TimedSegment};
+
+const DEFAULT_FILLER_WORDS: &[&str] = &["um", "uh", "uhm", "erm", "er", "hmm", "mm", "ah"];
+
+/// A non-speech tag like `[Music]`, with the time it appeared at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundEvent {
+    pub label: String,
+    pub start: f64,
+}
+
+/// Cleaned up captions: one segment per rebuilt sentence.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CleanedCaptions {
+    pub sentences: Vec<TimedSegment>,
+    /// Indices into `sentences` of the sentences starting a speaker turn
+    pub turn_starts: Vec<usize>,
+    pub sound_events: Vec<SoundEvent>,
+}
+
+impl CleanedCaptions {
+    /// The sentences joined with spaces, speaker turns separated by a
+    /// blank line.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for (index, sentence) in self.sentences.iter().enumerate() {
+            if index > 0 {
+                text.push_str(if self.turn_starts.contains(&index) {
+                    "\n\n"
+                } else {
+                    " "
+                });
+            }
+            text.push_str(&sentence.text);
+        }
+
+        text
+    }
+
+    /// `sound_events` (as `label@seconds` entries joined with `|`) and
+    /// `speaker_turns` metadata entries.
+    pub fn metadata(&self) -> SourceMetadata {
+        let sound_events = self
+            .sound_events
+            .iter()
+            .map(|e| format!("{}@{:.1}", e.label, e.start))
+            .collect::<Vec<String>>()
+            .join("|");
+
+        vec![
+            ("sound_events".to_string(), sound_events),
+            (
+                "speaker_turns".to_string(),
+                self.turn_starts.len().to_string(),
+            ),
+        ]
+    }
+}
+
+/// Cleans the artifacts of auto-generated (ASR) caption tracks.
+///
+/// - Sound tags (`[Music]`, `[Applause]`, `♪`) are removed from the text
+///   and kept as `SoundEvent`s.
+/// - `>>` speaker markers become speaker turn boundaries.
+/// - Filler words are dropped, and so are stutters: fragments cut off
+///   before the word ("th- the", "I- I") and repeated single letters
+///   ("I I"). Other repeated words ("that that", "had had") are kept.
+/// - Sentences are rebuilt from the timing: a pause of at least
+///   `sentence_gap` seconds or a speaker turn ends a sentence, which gets
+///   capitalised and terminated with a period unless it already has
+///   punctuation.
+#[derive(Debug, Clone)]
+pub struct CaptionCleaner {
+    pub sentence_gap: f64,
+    pub filler_words: Vec<String>,
+    sound_tag: Regex,
+}
+
+struct Word {
+    text: String,
+    start: f64,
+    end: f64,
+    /// The word follows a `>>` marker
+    turn_start: bool,
+}
+
+impl CaptionCleaner {
+    pub fn new() -> Self {
+        Self {
+            sentence_gap: 1.0,
+            filler_words: DEFAULT_FILLER_WORDS.iter().map(|w| w.to_string()).collect(),
+            sound_tag: Regex::new(r"\[\s*([^\]]*?)\s*\]").unwrap(),
+        }
+    }
+
+    pub fn with_sentence_gap(mut self, seconds: f64) -> Self {
+        self.sentence_gap = seconds;
+        self
+    }
+
+    pub fn with_filler_words(mut self, filler_words: &[&str]) -> Self {
+        self.filler_words = filler_words.iter().map(|w| w.to_lowercase()).collect();
+        self
+    }
+
+    pub fn clean(&self, segments: &[TimedSegment]) -> CleanedCaptions {
+        let mut cleaned = CleanedCaptions::default();
+        let words = self.words(segments, &mut cleaned.sound_events);
+
+        let mut sentence: Vec<&Word> = vec![];
+        for (index, word) in words.iter().enumerate() {
+            let ends_sentence = match words.get(index + 1) {
+                Some(next) => next.turn_start || next.start - word.end >= self.sentence_gap,
+                None => true,
+            };
+            sentence.push(word);
+
+            if ends_sentence || ends_with_terminal(&word.text) {
+                if sentence[0].turn_start {
+                    cleaned.turn_starts.push(cleaned.sentences.len());
+                }
+                cleaned.sentences.push(build_sentence(&sentence));
+                sentence.clear();
+            }
+        }
+
+        cleaned
+    }
+
+    /// Flattens the segments into timed words, dropping sound tags,
+    /// fillers and stutters. Words get the times of their segment.
+    fn words(&self, segments: &[TimedSegment], sound_events: &mut Vec<SoundEvent>) -> Vec<Word> {
+        let mut words: Vec<Word> = vec![];
+
+        for segment in segments {
+            for tag in self.sound_tag.captures_iter(&segment.text) {
+                if !tag[1].is_empty() {
+                    sound_events.push(SoundEvent {
+                        label: tag[1].to_lowercase(),
+                        start: segment.start,
+                    });
+                }
+            }
+            let duplicate = |e: &SoundEvent| e.label == "music" && e.start == segment.start;
+            if segment.text.contains('♪') && !sound_events.last().is_some_and(duplicate) {
+                sound_events.push(SoundEvent {
+                    label: "music".to_string(),
+                    start: segment.start,
+                });
+            }
+            let text = self.sound_tag.replace_all(&segment.text, " ");
+            let text = text.replace('♪', " ");
+
+            let mut turn_start = false;
+            for token in text.split_whitespace() {
+                if token.chars().all(|c| c == '>') {
+                    turn_start = true;
+                    continue;
+                }
+
+                let bare = bare_word(token);
+                if self.filler_words.contains(&bare) {
+                    continue;
+                }
+                match words.last_mut() {
+                    Some(last) if !turn_start && is_fragment_of(&last.text, &bare) => {
+                        last.text = token.to_string();
+                        last.end = segment.start + segment.duration;
+                        continue;
+                    }
+                    Some(last) if !turn_start && is_letter_repeat(&last.text, &bare) => {
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                words.push(Word {
+                    text: token.to_string(),
+                    start: segment.start,
+                    end: segment.start + segment.duration,
+                    turn_start: turn_start || words.is_empty(),
+                });
+                turn_start = false;
+            }
+        }
+
+        words
+    }
+}
+
+impl Default for CaptionCleaner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bare_word(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// `previous` is a fragment cut off before the word `bare` ("th-").
+fn is_fragment_of(previous: &str, bare: &str) -> bool {
+    let fragment = bare_word(previous);
+    previous.ends_with('-') && !fragment.is_empty() && bare.starts_with(&fragment)
+}
+
+/// `previous` is the same single letter as `bare` ("I I").
+fn is_letter_repeat(previous: &str, bare: &str) -> bool {
+    bare.chars().count() == 1 && bare_word(previous) == bare
+}
+
+fn ends_with_terminal(word: &str) -> bool {
+    word.ends_with(['.', '?', '!', '…'])
+}
+
+fn build_sentence(words: &[&Word]) -> TimedSegment {
+    let mut text = words
+        .iter()
+        .map(|w| w.text.as_str())
+        .collect::<Vec<&str>>()
+        .join(" ");
+
+    if !ends_with_terminal(&text) {
+        let trimmed_len = text.trim_end_matches([',', ';', ':', '-']).len();
+        text.truncate(trimmed_len);
+        text.push('.');
+    }
+    let mut chars = text.chars();
+    if let Some(first) = chars.next() {
+        text = first.to_uppercase().chain(chars).collect();
+    }
+
+    let start = words.first().map(|w| w.start).unwrap_or_default();
+    let end = words.last().map(|w| w.end).unwrap_or(start);
+    TimedSegment {
+        text,
+        start,
+        duration: (end - start).max(0.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str, start: f64, duration: f64) -> TimedSegment {
+        TimedSegment {
+            text: text.to_string(),
+            start,
+            duration,
+        }
+    }
+
+    #[test]
+    fn clean_test() {
+        let segments = vec![
+            segment("[Music]", 0.0, 3.0),
+            segment("so um today we- we look", 3.0, 2.0),
+            segment("at tokenizers", 5.0, 1.5),
+            segment("okay. >> can you show", 8.0, 2.0),
+            segment("an example [Applause]", 10.0, 2.0),
+            segment("♪ ♪", 12.0, 4.0),
+            segment("[Music] ♪", 16.0, 4.0),
+        ];
+
+        let cleaned = CaptionCleaner::new().clean(&segments);
+
+        assert_eq!(
+            cleaned.text(),
+            "So today we look at tokenizers. Okay.\n\nCan you show an example."
+        );
+        assert_eq!(cleaned.sentences[0].start, 3.0);
+        assert_eq!(cleaned.sentences[0].duration, 3.5);
+        assert_eq!(cleaned.turn_starts, vec![0, 2]);
+        assert_eq!(
+            cleaned.metadata()[0],
+            (
+                "sound_events".to_string(),
+                "music@0.0|applause@10.0|music@12.0|music@16.0".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn clean_stutters_test() {
+        let clean = |text: &str| {
+            CaptionCleaner::new()
+                .clean(&[segment(text, 0.0, 2.0)])
+                .text()
+        };
+
+        assert_eq!(clean("I- I I knew th- the answer"), "I knew the answer.");
+        assert_eq!(
+            clean("I knew that that was wrong"),
+            "I knew that that was wrong."
+        );
+        assert_eq!(
+            clean("we had had very very long days"),
+            "We had had very very long days."
+        );
+    }
+}
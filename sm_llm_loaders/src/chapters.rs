@@ -0,0 +1,170 @@
+use regex::Regex;
+
+use crate::SourceMetadata;
+
+/// Where the chapters of a video come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChapterSource {
+    /// Timestamps in the video description
+    Description,
+    /// Generated from the transcript
+    Generated,
+}
+
+impl ChapterSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChapterSource::Description => "description",
+            ChapterSource::Generated => "generated",
+        }
+    }
+}
+
+/// A section of a video, times in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start: f64,
+    /// `None` for the last chapter of a video of unknown duration
+    pub end: Option<f64>,
+    pub source: ChapterSource,
+}
+
+impl Chapter {
+    /// `chapter_index`, `chapter_title`, `chapter_start_seconds`,
+    /// `chapter_end_seconds` (when known) and `chapter_source` metadata
+    /// entries.
+    pub fn metadata(&self, index: usize) -> SourceMetadata {
+        let mut metadata = vec![
+            ("chapter_index".to_string(), index.to_string()),
+            ("chapter_title".to_string(), self.title.clone()),
+            (
+                "chapter_start_seconds".to_string(),
+                format!("{:.2}", self.start),
+            ),
+        ];
+        if let Some(end) = self.end {
+            metadata.push(("chapter_end_seconds".to_string(), format!("{end:.2}")));
+        }
+        metadata.push((
+            "chapter_source".to_string(),
+            self.source.as_str().to_string(),
+        ));
+
+        metadata
+    }
+
+    pub fn contains(&self, seconds: f64) -> bool {
+        seconds >= self.start && self.end.map(|end| seconds < end).unwrap_or(true)
+    }
+}
+
+/// Index of the chapter `seconds` falls in.
+pub fn find_chapter(chapters: &[Chapter], seconds: f64) -> Option<usize> {
+    chapters
+        .iter()
+        .position(|chapter| chapter.contains(seconds))
+}
+
+/// Parses the chapters of a video description, lines with a timestamp
+/// (`1:23`, `01:02:03`) before or after the title.
+///
+/// Follows the rules YouTube uses to show chapters: at least three
+/// timestamps, in ascending order, the first one at `0:00`. Descriptions
+/// that don't follow them give no chapters. The last chapter ends at
+/// `duration`, when given.
+pub fn parse_description_chapters(description: &str, duration: Option<f64>) -> Vec<Chapter> {
+    let timestamp = r"[\[(]?((?:\d{1,2}:)?\d{1,2}:\d{2})[\])]?";
+    let leading = Regex::new(&format!(
+        r"^\s*(?:[-•*]\s*)?{timestamp}\s*(?:[-–—:|.]\s*)?(.*?)\s*$"
+    ))
+    .unwrap();
+    let trailing = Regex::new(&format!(
+        r"^\s*(?:[-•*]\s*)?(.+?)\s*(?:[-–—:|]\s*)?{timestamp}\s*$"
+    ))
+    .unwrap();
+
+    let mut starts: Vec<(f64, String)> = vec![];
+    for line in description.lines() {
+        let (time, title) = if let Some(captures) = leading.captures(line) {
+            (captures[1].to_string(), captures[2].to_string())
+        } else if let Some(captures) = trailing.captures(line) {
+            (captures[2].to_string(), captures[1].to_string())
+        } else {
+            continue;
+        };
+
+        starts.push((parse_timestamp(&time), title));
+    }
+
+    let ascending = starts.windows(2).all(|pair| pair[0].0 < pair[1].0);
+    if starts.len() < 3 || starts[0].0 != 0.0 || !ascending {
+        return vec![];
+    }
+
+    let ends: Vec<Option<f64>> = starts
+        .iter()
+        .skip(1)
+        .map(|(start, _)| Some(*start))
+        .chain(std::iter::once(duration))
+        .collect();
+
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(|((start, title), end)| Chapter {
+            title,
+            start,
+            end,
+            source: ChapterSource::Description,
+        })
+        .collect()
+}
+
+/// Seconds of a `[h:]m:ss` timestamp.
+fn parse_timestamp(timestamp: &str) -> f64 {
+    timestamp.split(':').fold(0.0, |seconds, part| {
+        seconds * 60.0 + part.parse::<f64>().unwrap_or(0.0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_description_chapters_test() {
+        let description = "Tokenizers explained.\n\n\
+            0:00 Intro\n\
+            (1:05) - What is a token\n\
+            Splitting text | 12:34\n\
+            1:02:03 Outro\n\n\
+            Follow us!";
+
+        let chapters = parse_description_chapters(description, Some(4000.0));
+
+        let titles: Vec<&str> = chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Intro", "What is a token", "Splitting text", "Outro"]
+        );
+        assert_eq!(chapters[2].start, 754.0);
+        assert_eq!(chapters[2].end, Some(3723.0));
+        assert_eq!(chapters[3].end, Some(4000.0));
+        assert_eq!(find_chapter(&chapters, 800.0), Some(2));
+        assert_eq!(
+            chapters[1].metadata(1),
+            vec![
+                ("chapter_index".to_string(), "1".to_string()),
+                ("chapter_title".to_string(), "What is a token".to_string()),
+                ("chapter_start_seconds".to_string(), "65.00".to_string()),
+                ("chapter_end_seconds".to_string(), "754.00".to_string()),
+                ("chapter_source".to_string(), "description".to_string()),
+            ]
+        );
+
+        assert!(
+            parse_description_chapters("1:00 Not from the start\n2:00 B\n3:00 C", None).is_empty()
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt, TryStreamExt};
+
+use crate::incremental::{file_revision, record_loaded};
+use crate::{
+    content_hash, file_loader_for_path, Document, DocumentLoader, LoaderError, ManifestStore,
+    SourceMetadata,
+};
+
+/// Number of files loaded at the same time by default.
+pub const DEFAULT_DIRECTORY_CONCURRENCY: usize = 8;
+
+/// Loads every supported file found under a directory. The loader for
+/// each file is picked from its extension (see `file_loader_for_path`),
+/// files with unknown extensions are skipped.
+///
+/// Up to `concurrency` files are loaded at once, documents are returned
+/// in file path order regardless.
+pub struct DirectoryLoader {
+    pub path: PathBuf,
+    pub recursive: bool,
+    pub concurrency: usize,
+}
+
+impl DirectoryLoader {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            recursive: true,
+            concurrency: DEFAULT_DIRECTORY_CONCURRENCY,
+        }
+    }
+
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Lists the files this loader would visit, sorted by path.
+    pub async fn list_files(&self) -> Result<Vec<PathBuf>, LoaderError> {
+        let mut files = vec![];
+        let mut pending_dirs = vec![self.path.clone()];
+
+        while let Some(dir) = pending_dirs.pop() {
+            let mut entries = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    if self.recursive {
+                        pending_dirs.push(path);
+                    }
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+
+        Ok(files)
+    }
+
+    /// Whether `file` is in the part of the tree this loader visits.
+    fn covers(&self, file: &Path) -> bool {
+        match file.strip_prefix(&self.path) {
+            Ok(relative) => self.recursive || relative.components().count() == 1,
+            Err(_) => false,
+        }
+    }
+
+    /// Loads only the files that changed since they were last recorded
+    /// in `manifest`, like `IncrementalLoader` does for a single source:
+    /// files whose loader reports an unchanged `source_revision` (their
+    /// mtime) are skipped without being read, files that were read but
+    /// whose content hash didn't change are left out of the result.
+    ///
+    /// Once every file is loaded, the manifest entries of the files of
+    /// the directory that no longer exist are dropped.
+    pub async fn load_changed(
+        &self,
+        manifest: &mut ManifestStore,
+    ) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let mut changed = vec![];
+        let mut sources = HashSet::new();
+        for file in self.list_files().await? {
+            let Some(loader) = file_loader_for_path(&file) else {
+                continue;
+            };
+
+            let source = file.to_string_lossy().to_string();
+            sources.insert(source.clone());
+            let revision = loader.source_revision().await?;
+            let unchanged = revision
+                .as_deref()
+                .map(|revision| manifest.is_unchanged(&source, revision))
+                .unwrap_or(false);
+            if !unchanged {
+                changed.push((loader, source, revision));
+            }
+        }
+
+        let loaded: Vec<_> = stream::iter(changed)
+            .map(|(loader, source, revision)| async move {
+                let docs = loader.load().await?;
+                Ok::<_, LoaderError>((source, revision, docs))
+            })
+            .buffered(self.concurrency)
+            .try_collect()
+            .await?;
+
+        let mut docs = vec![];
+        for (source, revision, file_docs) in loaded {
+            if let Some(file_docs) = record_loaded(manifest, &source, revision, file_docs) {
+                docs.extend(file_docs);
+            }
+        }
+        manifest.prune(|source| !sources.contains(source) && self.covers(Path::new(source)));
+
+        Ok(docs)
+    }
+}
+
+async fn load_file(file: PathBuf) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+    match file_loader_for_path(&file) {
+        Some(loader) => loader.load().await,
+        None => Ok(vec![]),
+    }
+}
+
+#[async_trait]
+impl DocumentLoader<SourceMetadata> for DirectoryLoader {
+    async fn load(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let per_file: Vec<Vec<Document<SourceMetadata>>> = stream::iter(self.list_files().await?)
+            .map(load_file)
+            .buffered(self.concurrency)
+            .try_collect()
+            .await?;
+
+        Ok(per_file.into_iter().flatten().collect())
+    }
+
+    /// Hash over the paths and mtimes of all supported files, so a
+    /// directory where nothing changed can be skipped as a whole.
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        let mut revisions = vec![];
+        for file in self.list_files().await? {
+            if file_loader_for_path(&file).is_some() {
+                revisions.push(format!(
+                    "{}={}",
+                    file.display(),
+                    file_revision(&file).await?
+                ));
+            }
+        }
+
+        Ok(Some(format!("dir:{}", content_hash(&revisions.join("\n")))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::ManifestEntry;
+
+    fn set_mtime(path: &std::path::Path, seconds: u64) {
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn load_changed_test() {
+        let dir = std::env::temp_dir().join(format!("sm_llm_loaders_dir_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.txt"), dir.join("b.md"));
+        std::fs::write(&a, "first").unwrap();
+        std::fs::write(&b, "second").unwrap();
+        std::fs::write(dir.join("ignored.bin"), "binary").unwrap();
+        set_mtime(&a, 1_000);
+        set_mtime(&b, 1_000);
+
+        let loader = DirectoryLoader::new(&dir).with_concurrency(2);
+        let mut manifest = ManifestStore::open(dir.join("manifest.json")).unwrap();
+        assert_eq!(loader.load_changed(&mut manifest).await.unwrap().len(), 2);
+        assert!(loader.load_changed(&mut manifest).await.unwrap().is_empty());
+
+        // Touched only: read again, but not returned
+        set_mtime(&a, 2_000);
+        assert!(loader.load_changed(&mut manifest).await.unwrap().is_empty());
+        assert!(manifest.is_unchanged(&a.to_string_lossy(), &file_revision(&a).await.unwrap()));
+
+        std::fs::write(&b, "changed").unwrap();
+        set_mtime(&b, 3_000);
+        let docs = loader.load_changed(&mut manifest).await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].page_content, "changed");
+
+        // Deleted files are dropped from the manifest, other sources kept
+        let other = ManifestEntry {
+            revision: None,
+            content_hash: content_hash("other"),
+        };
+        manifest.record("https://example.com", other);
+        std::fs::remove_file(&b).unwrap();
+        assert!(loader.load_changed(&mut manifest).await.unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            manifest.sources().collect::<Vec<_>>(),
+            vec![a.to_string_lossy().as_ref(), "https://example.com"]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use async_trait::async_trait;
+use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    stamp_document, Document, DocumentLoader, LoaderError, SourceMetadata, CONTENT_HASH_KEY,
+};
+
+/// A step between loading and splitting that maps, filters or expands
+/// documents.
+///
+/// Closures taking and returning the documents are transformers too, so
+/// one-off steps don't need a type of their own.
+pub trait DocumentTransformer<Metadata>: Send + Sync {
+    fn transform(
+        &self,
+        docs: Vec<Document<Metadata>>,
+    ) -> Result<Vec<Document<Metadata>>, LoaderError>;
+}
+
+impl<Metadata, F> DocumentTransformer<Metadata> for F
+where
+    F: Fn(Vec<Document<Metadata>>) -> Result<Vec<Document<Metadata>>, LoaderError> + Send + Sync,
+{
+    fn transform(
+        &self,
+        docs: Vec<Document<Metadata>>,
+    ) -> Result<Vec<Document<Metadata>>, LoaderError> {
+        self(docs)
+    }
+}
+
+/// Runs transformers one after the other.
+pub struct TransformerChain<Metadata> {
+    transformers: Vec<Box<dyn DocumentTransformer<Metadata>>>,
+}
+
+impl<Metadata> TransformerChain<Metadata> {
+    pub fn new() -> Self {
+        Self {
+            transformers: vec![],
+        }
+    }
+
+    pub fn with<T: DocumentTransformer<Metadata> + 'static>(mut self, transformer: T) -> Self {
+        self.transformers.push(Box::new(transformer));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.transformers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transformers.is_empty()
+    }
+}
+
+impl<Metadata> Default for TransformerChain<Metadata> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Metadata> DocumentTransformer<Metadata> for TransformerChain<Metadata> {
+    fn transform(
+        &self,
+        docs: Vec<Document<Metadata>>,
+    ) -> Result<Vec<Document<Metadata>>, LoaderError> {
+        self.transformers
+            .iter()
+            .try_fold(docs, |docs, transformer| transformer.transform(docs))
+    }
+}
+
+/// Wraps a loader so its documents go through a transformer.
+pub struct TransformingLoader<L, T> {
+    pub loader: L,
+    pub transformer: T,
+}
+
+impl<L, T> TransformingLoader<L, T> {
+    pub fn new(loader: L, transformer: T) -> Self {
+        Self {
+            loader,
+            transformer,
+        }
+    }
+}
+
+#[async_trait]
+impl<Metadata, L, T> DocumentLoader<Metadata> for TransformingLoader<L, T>
+where
+    Metadata: serde::Serialize + serde::de::DeserializeOwned + Send + 'static,
+    L: DocumentLoader<Metadata> + Send + Sync,
+    T: DocumentTransformer<Metadata>,
+{
+    async fn load(&self) -> Result<Vec<Document<Metadata>>, LoaderError> {
+        let docs = self.loader.load().await?;
+        self.transformer.transform(docs)
+    }
+
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        self.loader.source_revision().await
+    }
+}
+
+/// Replaces the content of every document with `f(content)`, refreshing
+/// the `content_hash` of documents that have one.
+fn map_content<F: Fn(&str) -> String>(
+    mut docs: Vec<Document<SourceMetadata>>,
+    f: F,
+) -> Vec<Document<SourceMetadata>> {
+    for doc in docs.iter_mut() {
+        let content = f(&doc.page_content);
+        if content == doc.page_content {
+            continue;
+        }
+        doc.page_content = content;
+
+        let stamped = doc
+            .metadata
+            .iter()
+            .flatten()
+            .any(|(k, _)| k == CONTENT_HASH_KEY);
+        if stamped {
+            stamp_document(doc, None);
+        }
+    }
+
+    docs
+}
+
+/// Normalises the content to Unicode NFC, so that e.g. a precomposed
+/// `ά` and `α` followed by a combining accent compare (and embed) alike.
+#[derive(Debug, Clone, Default)]
+pub struct UnicodeNormalizer;
+
+impl DocumentTransformer<SourceMetadata> for UnicodeNormalizer {
+    fn transform(
+        &self,
+        docs: Vec<Document<SourceMetadata>>,
+    ) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        Ok(map_content(docs, |content| content.nfc().collect()))
+    }
+}
+
+/// Collapses runs of spaces and tabs to a single space and trims every
+/// line. Blank lines are kept as single paragraph breaks, or everything
+/// ends up on one line when `keep_line_breaks` is off.
+#[derive(Debug, Clone)]
+pub struct WhitespaceCollapser {
+    pub keep_line_breaks: bool,
+}
+
+impl WhitespaceCollapser {
+    pub fn new() -> Self {
+        Self {
+            keep_line_breaks: true,
+        }
+    }
+
+    pub fn with_line_breaks(mut self, keep_line_breaks: bool) -> Self {
+        self.keep_line_breaks = keep_line_breaks;
+        self
+    }
+
+    fn collapse(&self, content: &str) -> String {
+        if !self.keep_line_breaks {
+            return content.split_whitespace().collect::<Vec<&str>>().join(" ");
+        }
+
+        let mut collapsed = String::with_capacity(content.len());
+        let mut pending_break = "";
+        for line in content.lines() {
+            let line = line.split_whitespace().collect::<Vec<&str>>().join(" ");
+            if line.is_empty() {
+                if !collapsed.is_empty() {
+                    pending_break = "\n\n";
+                }
+                continue;
+            }
+
+            collapsed.push_str(pending_break);
+            collapsed.push_str(&line);
+            pending_break = "\n";
+        }
+
+        collapsed
+    }
+}
+
+impl Default for WhitespaceCollapser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocumentTransformer<SourceMetadata> for WhitespaceCollapser {
+    fn transform(
+        &self,
+        docs: Vec<Document<SourceMetadata>>,
+    ) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        Ok(map_content(docs, |content| self.collapse(content)))
+    }
+}
+
+/// Decodes HTML entities (`&amp;`, `&#39;`, ...) left in the content.
+#[derive(Debug, Clone, Default)]
+pub struct HtmlEntityDecoder;
+
+impl DocumentTransformer<SourceMetadata> for HtmlEntityDecoder {
+    fn transform(
+        &self,
+        docs: Vec<Document<SourceMetadata>>,
+    ) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        Ok(map_content(docs, |content| {
+            html_escape::decode_html_entities(content).to_string()
+        }))
+    }
+}
+
+/// Replaces every match of a regex, e.g. to redact e-mail addresses or
+/// tokens. The replacement may refer to capture groups (`$1`, `$name`).
+#[derive(Debug, Clone)]
+pub struct RegexRedactor {
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+impl RegexRedactor {
+    pub fn new(pattern: &str, replacement: &str) -> Result<Self, LoaderError> {
+        let pattern = Regex::new(pattern)
+            .map_err(|e| LoaderError::TransformError(format!("Invalid pattern: {e}")))?;
+
+        Ok(Self {
+            pattern,
+            replacement: replacement.to_string(),
+        })
+    }
+}
+
+impl DocumentTransformer<SourceMetadata> for RegexRedactor {
+    fn transform(
+        &self,
+        docs: Vec<Document<SourceMetadata>>,
+    ) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        Ok(map_content(docs, |content| {
+            self.pattern
+                .replace_all(content, self.replacement.as_str())
+                .to_string()
+        }))
+    }
+}
+
+/// Drops documents with fewer than `min_chars` characters once trimmed.
+#[derive(Debug, Clone)]
+pub struct MinLengthFilter {
+    pub min_chars: usize,
+}
+
+impl MinLengthFilter {
+    pub fn new(min_chars: usize) -> Self {
+        Self { min_chars }
+    }
+}
+
+impl<Metadata> DocumentTransformer<Metadata> for MinLengthFilter {
+    fn transform(
+        &self,
+        docs: Vec<Document<Metadata>>,
+    ) -> Result<Vec<Document<Metadata>>, LoaderError> {
+        Ok(docs
+            .into_iter()
+            .filter(|doc| doc.page_content.trim().chars().count() >= self.min_chars)
+            .collect())
+    }
+}
+
+type MetadataFn = Box<dyn Fn(&Document<SourceMetadata>) -> SourceMetadata + Send + Sync>;
+
+/// Adds metadata entries computed from each document, replacing entries
+/// with the same keys.
+pub struct MetadataEnricher {
+    enrich: MetadataFn,
+}
+
+impl MetadataEnricher {
+    pub fn new<F>(enrich: F) -> Self
+    where
+        F: Fn(&Document<SourceMetadata>) -> SourceMetadata + Send + Sync + 'static,
+    {
+        Self {
+            enrich: Box::new(enrich),
+        }
+    }
+
+    /// Adds the same `key`/`value` entry to every document.
+    pub fn constant(key: &str, value: &str) -> Self {
+        let entry = (key.to_string(), value.to_string());
+        Self::new(move |_| vec![entry.clone()])
+    }
+}
+
+impl DocumentTransformer<SourceMetadata> for MetadataEnricher {
+    fn transform(
+        &self,
+        mut docs: Vec<Document<SourceMetadata>>,
+    ) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        for doc in docs.iter_mut() {
+            let entries = (self.enrich)(doc);
+            let metadata = doc.metadata.get_or_insert_with(Vec::new);
+
+            metadata.retain(|(k, _)| !entries.iter().any(|(key, _)| key == k));
+            metadata.extend(entries);
+        }
+
+        Ok(docs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(content: &str) -> Document<SourceMetadata> {
+        let mut doc = Document {
+            page_content: content.to_string(),
+            metadata: Some(vec![("source_file".to_string(), "a.txt".to_string())]),
+        };
+        stamp_document(&mut doc, None);
+        doc
+    }
+
+    #[test]
+    fn chain_test() {
+        let chain = TransformerChain::new()
+            .with(HtmlEntityDecoder)
+            .with(UnicodeNormalizer)
+            .with(WhitespaceCollapser::new())
+            .with(RegexRedactor::new(r"[\w.]+@[\w.]+", "<email>").unwrap())
+            .with(MinLengthFilter::new(5))
+            .with(MetadataEnricher::new(|doc| {
+                vec![("chars".to_string(), doc.page_content.len().to_string())]
+            }));
+
+        let docs = chain
+            .transform(vec![
+                doc("  Mail  me at\tme@example.com &amp; \n\n\n  bye  "),
+                doc("a\u{0301}  "),
+            ])
+            .unwrap();
+
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].page_content, "Mail me at <email> &\n\nbye");
+        let metadata = docs[0].metadata.as_ref().unwrap();
+        assert!(metadata.contains(&(
+            CONTENT_HASH_KEY.to_string(),
+            crate::content_hash(&docs[0].page_content)
+        )));
+        assert!(metadata.contains(&("chars".to_string(), "25".to_string())));
+    }
+}
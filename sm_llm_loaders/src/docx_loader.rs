@@ -0,0 +1,297 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use crate::incremental::file_revision;
+use crate::zip_container::{parse_xml, ZipContainer};
+use crate::{stamp_document, Document, DocumentLoader, LoaderError, SourceMetadata};
+
+const WORDPROCESSING_NS: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+
+/// Core properties we keep, as (element name, metadata key).
+const CORE_PROPERTIES: &[(&str, &str)] = &[
+    ("title", "title"),
+    ("subject", "subject"),
+    ("creator", "author"),
+    ("keywords", "keywords"),
+    ("description", "description"),
+    ("lastModifiedBy", "last_modified_by"),
+    ("revision", "revision"),
+    ("created", "created"),
+    ("modified", "modified"),
+];
+
+/// Loads a `.docx` document, one document per section. A section is a
+/// heading (or the `Title`) and the paragraphs that follow it, the text
+/// before the first heading forms a section of its own.
+///
+/// Every document carries the core properties of the file (`title`,
+/// `author`, `created`, ...) and its `section_index`, `heading` and
+/// `heading_level`.
+pub struct DocxLoader {
+    pub path: PathBuf,
+}
+
+impl DocxLoader {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl DocumentLoader<SourceMetadata> for DocxLoader {
+    async fn load(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let revision = file_revision(&self.path).await?;
+        let bytes = tokio::fs::read(&self.path).await?;
+        let source_file = self.path.to_string_lossy().to_string();
+
+        let mut docs = tokio::task::spawn_blocking(move || parse_docx(bytes, &source_file))
+            .await
+            .map_err(|e| LoaderError::SourceReadError(e.to_string()))??;
+
+        for doc in docs.iter_mut() {
+            stamp_document(doc, Some(&revision));
+        }
+
+        Ok(docs)
+    }
+
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        file_revision(&self.path).await.map(Some)
+    }
+}
+
+struct Section {
+    heading: Option<String>,
+    heading_level: Option<usize>,
+    paragraphs: Vec<String>,
+}
+
+/// Parses a DOCX held in memory. `source_file` only ends up in the
+/// metadata.
+pub(crate) fn parse_docx(
+    bytes: Vec<u8>,
+    source_file: &str,
+) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+    let mut container = ZipContainer::new(bytes)?;
+
+    let mut file_metadata = vec![("source_file".to_string(), source_file.to_string())];
+    if container.has_entry("docProps/core.xml") {
+        let core_xml = container.read_string("docProps/core.xml")?;
+        file_metadata.extend(core_properties(&core_xml)?);
+    }
+
+    let document_xml = container.read_string("word/document.xml")?;
+    let document = parse_xml(&document_xml)?;
+
+    let mut sections = vec![Section {
+        heading: None,
+        heading_level: None,
+        paragraphs: vec![],
+    }];
+
+    for paragraph in document
+        .descendants()
+        .filter(|n| n.has_tag_name((WORDPROCESSING_NS, "p")))
+    {
+        let text = paragraph_text(paragraph);
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        match heading_level(paragraph) {
+            Some(level) => sections.push(Section {
+                heading: Some(text.trim().to_string()),
+                heading_level: Some(level),
+                paragraphs: vec![],
+            }),
+            None => {
+                if let Some(section) = sections.last_mut() {
+                    section.paragraphs.push(text);
+                }
+            }
+        }
+    }
+
+    let docs = sections
+        .into_iter()
+        .filter(|s| s.heading.is_some() || !s.paragraphs.is_empty())
+        .enumerate()
+        .map(|(section_index, section)| {
+            let mut metadata = file_metadata.clone();
+            metadata.push(("section_index".to_string(), section_index.to_string()));
+
+            let mut content = section.paragraphs.join("\n");
+            if let Some(heading) = section.heading {
+                content = format!("{heading}\n\n{content}").trim_end().to_string();
+                metadata.push(("heading".to_string(), heading));
+            }
+            if let Some(level) = section.heading_level {
+                metadata.push(("heading_level".to_string(), level.to_string()));
+            }
+
+            Document {
+                page_content: content,
+                metadata: Some(metadata),
+            }
+        })
+        .collect();
+
+    Ok(docs)
+}
+
+fn core_properties(core_xml: &str) -> Result<SourceMetadata, LoaderError> {
+    let core = parse_xml(core_xml)?;
+
+    let properties = CORE_PROPERTIES
+        .iter()
+        .filter_map(|(name, key)| {
+            core.root_element()
+                .children()
+                .find(|n| n.is_element() && n.tag_name().name() == *name)
+                .and_then(|n| n.text())
+                .map(|t| t.trim())
+                .filter(|t| !t.is_empty())
+                .map(|t| (key.to_string(), t.to_string()))
+        })
+        .collect();
+
+    Ok(properties)
+}
+
+/// Text of `paragraph`, without that of the paragraphs nested in it (text
+/// boxes, content controls), which are read as paragraphs of their own.
+fn paragraph_text(paragraph: roxmltree::Node) -> String {
+    let mut text = String::new();
+
+    let own_nodes = paragraph.descendants().filter(|node| {
+        node.ancestors()
+            .skip(1)
+            .find(|a| a.has_tag_name((WORDPROCESSING_NS, "p")))
+            == Some(paragraph)
+    });
+    for node in own_nodes {
+        if node.has_tag_name((WORDPROCESSING_NS, "t")) {
+            text.push_str(node.text().unwrap_or_default());
+        } else if node.has_tag_name((WORDPROCESSING_NS, "tab")) {
+            text.push('\t');
+        } else if node.has_tag_name((WORDPROCESSING_NS, "br")) {
+            text.push('\n');
+        }
+    }
+
+    text
+}
+
+/// Heading level of a paragraph from its style (`Title` is level 0,
+/// `Heading1` level 1, ...) or its outline level.
+fn heading_level(paragraph: roxmltree::Node) -> Option<usize> {
+    let properties = paragraph
+        .children()
+        .find(|n| n.has_tag_name((WORDPROCESSING_NS, "pPr")))?;
+
+    let style = properties
+        .children()
+        .find(|n| n.has_tag_name((WORDPROCESSING_NS, "pStyle")))
+        .and_then(|n| n.attribute((WORDPROCESSING_NS, "val")))
+        .map(|s| s.to_lowercase());
+
+    if let Some(style) = style {
+        if style == "title" {
+            return Some(0);
+        }
+        if let Some(level) = style.strip_prefix("heading") {
+            return Some(level.trim().parse().unwrap_or(1));
+        }
+    }
+
+    properties
+        .children()
+        .find(|n| n.has_tag_name((WORDPROCESSING_NS, "outlineLvl")))
+        .and_then(|n| n.attribute((WORDPROCESSING_NS, "val")))
+        .and_then(|l| l.parse::<usize>().ok())
+        // Level 9 is body text
+        .filter(|l| *l < 9)
+        .map(|l| l + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    fn docx_bytes(body: &str) -> Vec<u8> {
+        let document = format!(
+            r#"<?xml version="1.0"?><w:document xmlns:w="{WORDPROCESSING_NS}"><w:body>{body}</w:body></w:document>"#
+        );
+        let core = r#"<?xml version="1.0"?>
+            <cp:coreProperties
+                xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties"
+                xmlns:dc="http://purl.org/dc/elements/1.1/">
+              <dc:title>Report</dc:title>
+              <dc:creator>Ann</dc:creator>
+            </cp:coreProperties>"#;
+
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in [
+            ("word/document.xml", document.as_str()),
+            ("docProps/core.xml", core),
+        ] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn paragraph(properties: &str, text: &str) -> String {
+        format!("<w:p><w:pPr>{properties}</w:pPr><w:r><w:t>{text}</w:t></w:r></w:p>")
+    }
+
+    #[test]
+    fn parse_docx_test() {
+        let body = [
+            paragraph("", "Preface."),
+            paragraph(r#"<w:pStyle w:val="Title"/>"#, "Report"),
+            paragraph(r#"<w:pStyle w:val="Heading2"/>"#, "Results"),
+            paragraph(r#"<w:outlineLvl w:val="9"/>"#, "Body text, not a heading."),
+            paragraph(r#"<w:outlineLvl w:val="0"/>"#, "Outline heading"),
+            paragraph("", "Last words."),
+            format!(
+                "<w:p><w:r><w:t>Outer</w:t><w:pict><w:txbxContent>{}</w:txbxContent></w:pict></w:r></w:p>",
+                paragraph("", "Boxed")
+            ),
+        ]
+        .concat();
+
+        let docs = parse_docx(docx_bytes(&body), "report.docx").unwrap();
+
+        let contents: Vec<&str> = docs.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec![
+                "Preface.",
+                "Report",
+                "Results\n\nBody text, not a heading.",
+                "Outline heading\n\nLast words.\nOuter\nBoxed",
+            ]
+        );
+
+        let metadata = docs[3].metadata.clone().unwrap();
+        for entry in [
+            ("title", "Report"),
+            ("author", "Ann"),
+            ("section_index", "3"),
+            ("heading_level", "1"),
+        ] {
+            assert!(
+                metadata.contains(&(entry.0.to_string(), entry.1.to_string())),
+                "{entry:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use crate::html_text::html_to_text;
+use crate::incremental::file_revision;
+use crate::zip_container::{parse_xml, ZipContainer};
+use crate::{stamp_document, Document, DocumentLoader, LoaderError, SourceMetadata};
+
+/// Loads an `.epub` book, one document per chapter of the spine.
+///
+/// Every document carries the book `title`, `author` and `language` from
+/// the OPF metadata, plus the `chapter_index`, `chapter_href` and, when
+/// the chapter has one, the `chapter_title`.
+pub struct EpubLoader {
+    pub path: PathBuf,
+}
+
+impl EpubLoader {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl DocumentLoader<SourceMetadata> for EpubLoader {
+    async fn load(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let revision = file_revision(&self.path).await?;
+        let bytes = tokio::fs::read(&self.path).await?;
+        let source_file = self.path.to_string_lossy().to_string();
+
+        let mut docs = tokio::task::spawn_blocking(move || parse_epub(bytes, &source_file))
+            .await
+            .map_err(|e| LoaderError::SourceReadError(e.to_string()))??;
+
+        for doc in docs.iter_mut() {
+            stamp_document(doc, Some(&revision));
+        }
+
+        Ok(docs)
+    }
+
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        file_revision(&self.path).await.map(Some)
+    }
+}
+
+/// Parses an EPUB held in memory. `source_file` only ends up in the
+/// metadata.
+pub(crate) fn parse_epub(
+    bytes: Vec<u8>,
+    source_file: &str,
+) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+    let mut container = ZipContainer::new(bytes)?;
+
+    let container_xml = container.read_string("META-INF/container.xml")?;
+    let opf_path = parse_xml(&container_xml)?
+        .descendants()
+        .find(|n| n.tag_name().name() == "rootfile")
+        .and_then(|n| n.attribute("full-path"))
+        .map(String::from)
+        .ok_or_else(|| LoaderError::SourceReadError("EPUB without a rootfile".to_string()))?;
+    let opf_dir = opf_path
+        .rsplit_once('/')
+        .map(|(dir, _)| format!("{dir}/"))
+        .unwrap_or_default();
+
+    let opf_xml = container.read_string(&opf_path)?;
+    let opf = parse_xml(&opf_xml)?;
+
+    let dc_values = |name: &str| {
+        opf.descendants()
+            .filter(|n| n.is_element() && n.tag_name().name() == name)
+            .filter_map(|n| n.text())
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect::<Vec<String>>()
+    };
+
+    let mut book_metadata = vec![("source_file".to_string(), source_file.to_string())];
+    if let Some(title) = dc_values("title").into_iter().next() {
+        book_metadata.push(("title".to_string(), title));
+    }
+    let authors = dc_values("creator");
+    if !authors.is_empty() {
+        book_metadata.push(("author".to_string(), authors.join("; ")));
+    }
+    if let Some(language) = dc_values("language").into_iter().next() {
+        book_metadata.push(("language".to_string(), language));
+    }
+
+    let manifest: HashMap<&str, &str> = opf
+        .descendants()
+        .filter(|n| n.tag_name().name() == "item")
+        .filter_map(|n| Some((n.attribute("id")?, n.attribute("href")?)))
+        .collect();
+
+    let spine = opf
+        .descendants()
+        .filter(|n| n.tag_name().name() == "itemref")
+        .filter_map(|n| n.attribute("idref"))
+        .filter_map(|idref| manifest.get(idref).copied());
+
+    let mut docs = vec![];
+    for (chapter_index, href) in spine.enumerate() {
+        let entry_path = resolve_href(&opf_dir, href);
+        if !container.has_entry(&entry_path) {
+            return Err(LoaderError::SourceReadError(format!(
+                "EPUB spine item {href} is missing ({entry_path})"
+            )));
+        }
+
+        let chapter = html_to_text(&container.read_string(&entry_path)?);
+        if chapter.text.is_empty() {
+            continue;
+        }
+
+        let mut metadata = book_metadata.clone();
+        metadata.push(("chapter_index".to_string(), chapter_index.to_string()));
+        metadata.push(("chapter_href".to_string(), href.to_string()));
+        if let Some(chapter_title) = chapter.title {
+            metadata.push(("chapter_title".to_string(), chapter_title));
+        }
+
+        docs.push(Document {
+            page_content: chapter.text,
+            metadata: Some(metadata),
+        });
+    }
+
+    Ok(docs)
+}
+
+/// Path in the container of a manifest `href`, relative to the directory
+/// of the OPF: without its fragment, percent-decoded, and with `.` and
+/// `..` segments resolved.
+fn resolve_href(opf_dir: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or(href);
+
+    let mut segments: Vec<String> = vec![];
+    for segment in format!("{opf_dir}{href}").split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(percent_decode(segment)),
+        }
+    }
+
+    segments.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    fn zip_bytes(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in entries {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    const CONTAINER_XML: &str = r#"<?xml version="1.0"?>
+        <container xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+          <rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles>
+        </container>"#;
+
+    fn opf_xml(spine_hrefs: &[&str]) -> String {
+        let items: String = spine_hrefs
+            .iter()
+            .enumerate()
+            .map(|(i, href)| format!(r#"<item id="c{i}" href="{href}"/>"#))
+            .collect();
+        let itemrefs: String = (0..spine_hrefs.len())
+            .map(|i| format!(r#"<itemref idref="c{i}"/>"#))
+            .collect();
+
+        format!(
+            r#"<?xml version="1.0"?>
+            <package xmlns="http://www.idpf.org/2007/opf" xmlns:dc="http://purl.org/dc/elements/1.1/">
+              <metadata>
+                <dc:title>A Book</dc:title>
+                <dc:creator>Ann</dc:creator>
+                <dc:creator>Bob</dc:creator>
+                <dc:language>el</dc:language>
+              </metadata>
+              <manifest>{items}</manifest>
+              <spine>{itemrefs}</spine>
+            </package>"#
+        )
+    }
+
+    fn chapter(title: &str, text: &str) -> String {
+        format!("<html><head><title>{title}</title></head><body><p>{text}</p></body></html>")
+    }
+
+    #[test]
+    fn parse_epub_test() {
+        let opf = opf_xml(&["Text/Chapter%201.xhtml#start", "../Extra/./end.xhtml"]);
+        let first = chapter("One", "First chapter.");
+        let last = chapter("End", "The end.");
+        let bytes = zip_bytes(&[
+            ("META-INF/container.xml", CONTAINER_XML),
+            ("OEBPS/content.opf", &opf),
+            ("OEBPS/Text/Chapter 1.xhtml", &first),
+            ("Extra/end.xhtml", &last),
+        ]);
+
+        let docs = parse_epub(bytes, "book.epub").unwrap();
+        assert_eq!(docs.len(), 2);
+        assert!(docs[0].page_content.contains("First chapter."));
+        assert!(docs[1].page_content.contains("The end."));
+
+        let metadata = docs[1].metadata.clone().unwrap();
+        for entry in [
+            ("title", "A Book"),
+            ("author", "Ann; Bob"),
+            ("language", "el"),
+            ("chapter_index", "1"),
+            ("chapter_href", "../Extra/./end.xhtml"),
+        ] {
+            assert!(
+                metadata.contains(&(entry.0.to_string(), entry.1.to_string())),
+                "{entry:?}"
+            );
+        }
+
+        let opf = opf_xml(&["Text/missing.xhtml"]);
+        let bytes = zip_bytes(&[
+            ("META-INF/container.xml", CONTAINER_XML),
+            ("OEBPS/content.opf", &opf),
+        ]);
+        assert!(matches!(
+            parse_epub(bytes, "book.epub"),
+            Err(LoaderError::SourceReadError(_))
+        ));
+    }
+}
@@ -0,0 +1,108 @@
+use scraper::{ElementRef, Html, Selector};
+
+/// Elements whose text never makes it into the extracted content.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "head", "iframe",
+];
+
+/// Elements that start a new line in the extracted content.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "br",
+    "li",
+    "ul",
+    "ol",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "tr",
+    "table",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "nav",
+    "aside",
+    "main",
+    "blockquote",
+    "pre",
+    "hr",
+    "dd",
+    "dt",
+    "figcaption",
+];
+
+/// Text extracted from an HTML page.
+pub(crate) struct HtmlText {
+    pub title: Option<String>,
+    pub text: String,
+    /// `href` of every link, as written in the page
+    pub links: Vec<String>,
+}
+
+/// Naive HTML to text conversion: drops scripts/styles, keeps block
+/// structure as newlines and collapses all other whitespace.
+pub(crate) fn html_to_text(html: &str) -> HtmlText {
+    let document = Html::parse_document(html);
+
+    let title = Selector::parse("title")
+        .ok()
+        .and_then(|s| document.select(&s).next())
+        .map(|t| collapse_whitespace(&t.text().collect::<String>()))
+        .filter(|t| !t.is_empty());
+
+    let mut raw = String::new();
+    walk(document.root_element(), &mut raw);
+
+    let text = raw
+        .lines()
+        .map(collapse_whitespace)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let links = Selector::parse("a[href]")
+        .map(|s| {
+            document
+                .select(&s)
+                .filter_map(|a| a.value().attr("href"))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    HtmlText { title, text, links }
+}
+
+fn walk(element: ElementRef, out: &mut String) {
+    let name = element.value().name();
+    if SKIPPED_ELEMENTS.contains(&name) {
+        return;
+    }
+
+    let is_block = BLOCK_ELEMENTS.contains(&name);
+    if is_block {
+        out.push('\n');
+    }
+
+    for child in element.children() {
+        if let Some(child_element) = ElementRef::wrap(child) {
+            walk(child_element, out);
+        } else if let Some(text) = child.value().as_text() {
+            out.push_str(text);
+            out.push(' ');
+        }
+    }
+
+    if is_block {
+        out.push('\n');
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
@@ -0,0 +1,371 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{BoxedDocumentLoader, Document, LoaderError, SourceMetadata};
+
+pub const CONTENT_HASH_KEY: &str = "content_hash";
+pub const SOURCE_REVISION_KEY: &str = "source_revision";
+
+/// Stable (SHA-256, hex encoded) hash of a piece of content.
+pub fn content_hash(content: &str) -> String {
+    let digest = Sha256::digest(content.as_bytes());
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Hash over the content of all `docs`, in order.
+pub fn documents_hash<M>(docs: &[Document<M>]) -> String {
+    let mut hasher = Sha256::new();
+    for doc in docs {
+        hasher.update(content_hash(&doc.page_content).as_bytes());
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Adds the `content_hash` and, when known, the `source_revision`
+/// entries to the metadata of `doc`, replacing any previous ones.
+pub fn stamp_document(doc: &mut Document<SourceMetadata>, revision: Option<&str>) {
+    let hash = content_hash(&doc.page_content);
+    let metadata = doc.metadata.get_or_insert_with(Vec::new);
+
+    metadata
+        .retain(|(k, _)| k != CONTENT_HASH_KEY && (revision.is_none() || k != SOURCE_REVISION_KEY));
+    metadata.push((CONTENT_HASH_KEY.to_string(), hash));
+    if let Some(revision) = revision {
+        metadata.push((SOURCE_REVISION_KEY.to_string(), revision.to_string()));
+    }
+}
+
+/// Revision of a local file: its modification time in nanoseconds since
+/// the unix epoch.
+pub(crate) async fn file_revision<P: AsRef<Path>>(path: P) -> Result<String, LoaderError> {
+    modified_revision(&tokio::fs::metadata(path).await?)
+}
+
+/// `file_revision` from the metadata of an open file, for blocking code.
+pub(crate) fn modified_revision(metadata: &std::fs::Metadata) -> Result<String, LoaderError> {
+    let modified = metadata.modified()?;
+    let nanos = modified
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| LoaderError::SourceReadError(e.to_string()))?
+        .as_nanos();
+
+    Ok(format!("mtime:{nanos}"))
+}
+
+fn metadata_value<'a>(docs: &'a [Document<SourceMetadata>], key: &str) -> Option<&'a str> {
+    docs.iter()
+        .filter_map(|d| d.metadata.as_ref())
+        .flat_map(|m| m.iter())
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub revision: Option<String>,
+    pub content_hash: String,
+}
+
+/// Remembers the revision and content hash of every source seen by a
+/// previous run, persisted as a JSON file.
+#[derive(Debug)]
+pub struct ManifestStore {
+    path: PathBuf,
+    entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl ManifestStore {
+    /// Opens the manifest at `path`, starting empty if the file does
+    /// not exist yet.
+    pub fn open<T: Into<PathBuf>>(path: T) -> Result<Self, LoaderError> {
+        let path = path.into();
+
+        let entries = if path.exists() {
+            let content = std::fs::read_to_string(&path)?;
+            serde_json::from_str(&content).map_err(|e| {
+                LoaderError::ManifestError(format!("Invalid manifest {path:?}: {e}"))
+            })?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self { path, entries })
+    }
+
+    pub fn get(&self, source: &str) -> Option<&ManifestEntry> {
+        self.entries.get(source)
+    }
+
+    /// Whether `source` was recorded with exactly this `revision`.
+    pub fn is_unchanged(&self, source: &str, revision: &str) -> bool {
+        self.entries.get(source).and_then(|e| e.revision.as_deref()) == Some(revision)
+    }
+
+    pub fn record(&mut self, source: &str, entry: ManifestEntry) {
+        self.entries.insert(source.to_string(), entry);
+    }
+
+    pub fn remove(&mut self, source: &str) -> Option<ManifestEntry> {
+        self.entries.remove(source)
+    }
+
+    /// Removes the entries of the sources `is_stale` returns `true` for,
+    /// returning those sources.
+    pub fn prune<F: FnMut(&str) -> bool>(&mut self, mut is_stale: F) -> Vec<String> {
+        let stale: Vec<String> = self
+            .entries
+            .keys()
+            .filter(|source| is_stale(source))
+            .cloned()
+            .collect();
+        for source in stale.iter() {
+            self.entries.remove(source);
+        }
+
+        stale
+    }
+
+    pub fn sources(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|k| k.as_str())
+    }
+
+    /// Writes the manifest back to disk. The file is replaced atomically
+    /// so an interrupted run never leaves a truncated manifest behind.
+    pub fn save(&self) -> Result<(), LoaderError> {
+        let content = serde_json::to_string_pretty(&self.entries)
+            .map_err(|e| LoaderError::ManifestError(e.to_string()))?;
+
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Wraps a loader so that re-runs skip its source when it has not
+/// changed since it was last recorded in a `ManifestStore`.
+///
+/// The cheap `source_revision` of the loader is checked first. When the
+/// loader can't provide one, the source is loaded and its content hash is
+/// compared instead.
+pub struct IncrementalLoader {
+    source: String,
+    loader: BoxedDocumentLoader,
+}
+
+impl IncrementalLoader {
+    pub fn new<T: Into<String>>(source: T, loader: BoxedDocumentLoader) -> Self {
+        Self {
+            source: source.into(),
+            loader,
+        }
+    }
+
+    /// Loads the source if it changed, recording it in `manifest`.
+    /// Returns `None` when the source is unchanged.
+    pub async fn load_if_changed(
+        &self,
+        manifest: &mut ManifestStore,
+    ) -> Result<Option<Vec<Document<SourceMetadata>>>, LoaderError> {
+        let revision = self.loader.source_revision().await?;
+        if let Some(revision) = &revision {
+            if manifest.is_unchanged(&self.source, revision) {
+                return Ok(None);
+            }
+        }
+
+        let docs = self.loader.load().await?;
+
+        Ok(record_loaded(manifest, &self.source, revision, docs))
+    }
+}
+
+/// Records freshly loaded `docs` of `source` in `manifest`, returning
+/// them unless their content hash is the one already recorded (e.g. a
+/// file that was only touched). The revision defaults to the
+/// `source_revision` metadata of the documents.
+pub(crate) fn record_loaded(
+    manifest: &mut ManifestStore,
+    source: &str,
+    revision: Option<String>,
+    docs: Vec<Document<SourceMetadata>>,
+) -> Option<Vec<Document<SourceMetadata>>> {
+    let hash = documents_hash(&docs);
+    let revision =
+        revision.or_else(|| metadata_value(&docs, SOURCE_REVISION_KEY).map(String::from));
+
+    let unchanged = manifest.get(source).map(|e| &e.content_hash) == Some(&hash);
+
+    manifest.record(
+        source,
+        ManifestEntry {
+            revision,
+            content_hash: hash,
+        },
+    );
+
+    match unchanged {
+        true => None,
+        false => Some(docs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::DocumentLoader;
+
+    /// Loads a shared text, with a shared revision, counting the loads.
+    #[derive(Clone, Default)]
+    struct FakeLoader {
+        content: Arc<Mutex<String>>,
+        revision: Arc<Mutex<Option<String>>>,
+        loads: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl DocumentLoader<SourceMetadata> for FakeLoader {
+        async fn load(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![Document {
+                page_content: self.content.lock().unwrap().clone(),
+                metadata: None,
+            }])
+        }
+
+        async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+            Ok(self.revision.lock().unwrap().clone())
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sm_llm_loaders_{name}_{}.json", std::process::id()))
+    }
+
+    #[test]
+    fn stamp_document_test() {
+        let mut doc = Document {
+            page_content: "hello".to_string(),
+            metadata: Some(vec![
+                (CONTENT_HASH_KEY.to_string(), "stale".to_string()),
+                (SOURCE_REVISION_KEY.to_string(), "r1".to_string()),
+                ("title".to_string(), "Hello".to_string()),
+            ]),
+        };
+
+        stamp_document(&mut doc, None);
+        let metadata = doc.metadata.clone().unwrap();
+        assert!(metadata.contains(&(CONTENT_HASH_KEY.to_string(), content_hash("hello"))));
+        assert!(metadata.contains(&(SOURCE_REVISION_KEY.to_string(), "r1".to_string())));
+        assert_eq!(metadata.len(), 3);
+
+        stamp_document(&mut doc, Some("r2"));
+        let revisions: Vec<&str> = doc
+            .metadata
+            .iter()
+            .flatten()
+            .filter(|(k, _)| k == SOURCE_REVISION_KEY)
+            .map(|(_, v)| v.as_str())
+            .collect();
+        assert_eq!(revisions, vec!["r2"]);
+    }
+
+    #[test]
+    fn manifest_store_test() {
+        let path = temp_path("manifest");
+        let _ = std::fs::remove_file(&path);
+
+        let mut manifest = ManifestStore::open(&path).unwrap();
+        assert_eq!(manifest.sources().count(), 0);
+        let entry = ManifestEntry {
+            revision: Some("r1".to_string()),
+            content_hash: content_hash("hello"),
+        };
+        manifest.record("file:///a.txt", entry.clone());
+        manifest.save().unwrap();
+
+        let reopened = ManifestStore::open(&path).unwrap();
+        assert_eq!(reopened.get("file:///a.txt"), Some(&entry));
+        assert!(reopened.is_unchanged("file:///a.txt", "r1"));
+        assert!(!reopened.is_unchanged("file:///a.txt", "r2"));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            ManifestStore::open(&path),
+            Err(LoaderError::ManifestError(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn load_if_changed_test() {
+        let mut manifest = ManifestStore::open(temp_path("incremental")).unwrap();
+        let fake = FakeLoader::default();
+        *fake.content.lock().unwrap() = "v1".to_string();
+        *fake.revision.lock().unwrap() = Some("r1".to_string());
+        let loader = IncrementalLoader::new("fake://source", Box::new(fake.clone()));
+
+        assert!(loader
+            .load_if_changed(&mut manifest)
+            .await
+            .unwrap()
+            .is_some());
+
+        // Same revision: not even loaded
+        assert!(loader
+            .load_if_changed(&mut manifest)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(fake.loads.load(Ordering::SeqCst), 1);
+
+        // New revision, same content (e.g. touched): loaded but skipped
+        *fake.revision.lock().unwrap() = Some("r2".to_string());
+        assert!(loader
+            .load_if_changed(&mut manifest)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(fake.loads.load(Ordering::SeqCst), 2);
+        assert!(manifest.is_unchanged("fake://source", "r2"));
+
+        // Changed content
+        *fake.revision.lock().unwrap() = Some("r3".to_string());
+        *fake.content.lock().unwrap() = "v2".to_string();
+        let docs = loader
+            .load_if_changed(&mut manifest)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(docs[0].page_content, "v2");
+
+        // Without revisions only the content hash tells
+        *fake.revision.lock().unwrap() = None;
+        assert!(loader
+            .load_if_changed(&mut manifest)
+            .await
+            .unwrap()
+            .is_none());
+        *fake.content.lock().unwrap() = "v3".to_string();
+        assert!(loader
+            .load_if_changed(&mut manifest)
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
@@ -1,9 +1,41 @@
+mod archive_loader;
+mod caption_cleaner;
+mod chapters;
+mod directory_loader;
+mod document_transformer;
+mod docx_loader;
+mod epub_loader;
+mod html_text;
+mod incremental;
+mod loader_registry;
 mod pdf_loader;
+mod repository_loader;
+mod segment_merger;
+mod tabular_loader;
 mod text_file_loader;
 mod traits;
+mod web_crawler_loader;
+mod web_page_loader;
 mod youtube_captions_loader;
+mod youtube_playlist_loader;
+mod zip_container;
 
+pub use archive_loader::*;
+pub use caption_cleaner::*;
+pub use chapters::*;
+pub use directory_loader::*;
+pub use document_transformer::*;
+pub use docx_loader::*;
+pub use epub_loader::*;
+pub use incremental::*;
+pub use loader_registry::*;
 pub use pdf_loader::*;
+pub use repository_loader::*;
+pub use segment_merger::*;
+pub use tabular_loader::*;
 pub use text_file_loader::*;
 pub use traits::*;
+pub use web_crawler_loader::*;
+pub use web_page_loader::*;
 pub use youtube_captions_loader::*;
+pub use youtube_playlist_loader::*;
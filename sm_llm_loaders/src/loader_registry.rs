@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use url::Url;
+
+use crate::{
+    is_archive_path, ArchiveLoader, BoxedDocumentLoader, DirectoryLoader, Document, DocxLoader,
+    EpubLoader, IncrementalLoader, LoaderError, ManifestStore, NaivePdfFileLoader,
+    RepositoryLoader, SourceMetadata, TabularFormat, TabularLoader, TextFileLoader, WebPageLoader,
+    YoutubeCaptionsLoader, YoutubePlaylistLoader,
+};
+
+/// Builds a loader for a source string. The factory receives the whole
+/// source, scheme included.
+pub type LoaderFactory =
+    Box<dyn Fn(&str) -> Result<BoxedDocumentLoader, LoaderError> + Send + Sync>;
+
+pub(crate) const TEXT_FILE_EXTENSIONS: &[&str] =
+    &["txt", "text", "md", "markdown", "rst", "srt", "vtt"];
+
+/// Resolves source strings to the right `DocumentLoader`.
+///
+/// Sources look like `scheme://rest`. The default registry knows about:
+/// - `yt://VIDEO_ID` and any YouTube video URL
+/// - `yt-playlist://PLAYLIST_ID` and any YouTube playlist URL
+/// - `file:///path/to/file.pdf` (and bare paths without a scheme)
+/// - `dir:///path/to/dir`
+/// - `repo:///path/to/repo` (split into top-level items)
+/// - `http://…` / `https://…`
+///
+/// Custom schemes can be added with `register`, which also replaces the
+/// factory of an existing scheme.
+pub struct LoaderRegistry {
+    factories: HashMap<String, LoaderFactory>,
+}
+
+impl LoaderRegistry {
+    /// Creates a registry with no schemes registered.
+    pub fn empty() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Creates a registry with the loaders of this crate registered.
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+
+        registry.register("yt", |source| {
+            let video_id = strip_scheme(source);
+            Ok(Box::new(YoutubeCaptionsLoader::new(video_id.to_string())))
+        });
+        registry.register("yt-playlist", |source| {
+            let playlist_id = strip_scheme(source);
+            Ok(Box::new(YoutubePlaylistLoader::new(
+                playlist_id.to_string(),
+            )))
+        });
+        registry.register("file", |source| {
+            let path = strip_scheme(source);
+            file_loader_for_path(Path::new(path))
+                .ok_or_else(|| LoaderError::UnsupportedSource(source.to_string()))
+        });
+        registry.register("dir", |source| {
+            let path = strip_scheme(source);
+            Ok(Box::new(DirectoryLoader::new(path)))
+        });
+        registry.register("repo", |source| {
+            let path = strip_scheme(source);
+            Ok(Box::new(RepositoryLoader::new(path).with_item_split(true)))
+        });
+        registry.register("http", |source| {
+            Ok(Box::new(WebPageLoader::new(source.to_string())))
+        });
+        registry.register("https", |source| {
+            Ok(Box::new(WebPageLoader::new(source.to_string())))
+        });
+
+        registry
+    }
+
+    pub fn register<F>(&mut self, scheme: &str, factory: F)
+    where
+        F: Fn(&str) -> Result<BoxedDocumentLoader, LoaderError> + Send + Sync + 'static,
+    {
+        self.factories
+            .insert(scheme.to_lowercase(), Box::new(factory));
+    }
+
+    pub fn schemes(&self) -> Vec<&str> {
+        let mut schemes: Vec<&str> = self.factories.keys().map(|s| s.as_str()).collect();
+        schemes.sort();
+        schemes
+    }
+
+    /// Picks the loader for `source`. YouTube URLs are rewritten to the
+    /// `yt`/`yt-playlist` schemes first, sources without a scheme are
+    /// treated as local paths.
+    pub fn resolve(&self, source: &str) -> Result<BoxedDocumentLoader, LoaderError> {
+        let source = source.trim();
+
+        let normalized = match youtube_source(source) {
+            Some(yt_source) => yt_source,
+            None if !source.contains("://") => {
+                if Path::new(source).is_dir() {
+                    format!("dir://{source}")
+                } else {
+                    format!("file://{source}")
+                }
+            }
+            None => source.to_string(),
+        };
+
+        let (scheme, _) = normalized
+            .split_once("://")
+            .ok_or_else(|| LoaderError::UnsupportedSource(source.to_string()))?;
+
+        let factory = self
+            .factories
+            .get(&scheme.to_lowercase())
+            .ok_or_else(|| LoaderError::UnsupportedSource(source.to_string()))?;
+
+        factory(&normalized)
+    }
+
+    /// Resolves and loads every source in order.
+    pub async fn load_all<S: AsRef<str>>(
+        &self,
+        sources: &[S],
+    ) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let mut docs = vec![];
+        for source in sources {
+            let loader = self.resolve(source.as_ref())?;
+            docs.extend(loader.load().await?);
+        }
+
+        Ok(docs)
+    }
+
+    /// Like `load_all`, but skips the sources that did not change since
+    /// they were last recorded in `manifest`.
+    pub async fn load_changed<S: AsRef<str>>(
+        &self,
+        sources: &[S],
+        manifest: &mut ManifestStore,
+    ) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let mut docs = vec![];
+        for source in sources {
+            let loader = IncrementalLoader::new(source.as_ref(), self.resolve(source.as_ref())?);
+            if let Some(changed) = loader.load_if_changed(manifest).await? {
+                docs.extend(changed);
+            }
+        }
+
+        Ok(docs)
+    }
+}
+
+impl Default for LoaderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Picks a file loader from the extension of `path`.
+pub fn file_loader_for_path(path: &Path) -> Option<BoxedDocumentLoader> {
+    if is_archive_path(path) {
+        return Some(Box::new(ArchiveLoader::new(path)));
+    }
+
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+
+    if extension == "pdf" {
+        Some(Box::new(NaivePdfFileLoader::new(path)))
+    } else if extension == "epub" {
+        Some(Box::new(EpubLoader::new(path)))
+    } else if extension == "docx" {
+        Some(Box::new(DocxLoader::new(path)))
+    } else if let Some(format) = TabularFormat::from_path(path) {
+        Some(Box::new(TabularLoader::new(path, format)))
+    } else if TEXT_FILE_EXTENSIONS.contains(&extension.as_str()) {
+        Some(Box::new(TextFileLoader::new(
+            path.to_string_lossy().to_string(),
+        )))
+    } else {
+        None
+    }
+}
+
+fn strip_scheme(source: &str) -> &str {
+    source.split_once("://").map_or(source, |(_, rest)| rest)
+}
+
+/// Maps YouTube watch/short/embed/playlist URLs to `yt://` and
+/// `yt-playlist://` sources.
+fn youtube_source(source: &str) -> Option<String> {
+    let url = Url::parse(source).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+
+    let host = url
+        .host_str()?
+        .trim_start_matches("www.")
+        .trim_start_matches("m.");
+    let mut segments = url.path_segments()?.filter(|s| !s.is_empty());
+    let query_param = |key: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+    };
+
+    match host {
+        "youtu.be" => segments.next().map(|id| format!("yt://{id}")),
+        "youtube.com" | "music.youtube.com" => match segments.next()? {
+            "watch" => query_param("v").map(|id| format!("yt://{id}")),
+            "shorts" | "embed" | "live" | "v" => segments.next().map(|id| format!("yt://{id}")),
+            "playlist" => query_param("list").map(|id| format!("yt-playlist://{id}")),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// A registry recording the sources its factories get.
+    fn recording_registry(schemes: &[&str]) -> (LoaderRegistry, Arc<Mutex<Vec<String>>>) {
+        let resolved: Arc<Mutex<Vec<String>>> = Arc::default();
+        let mut registry = LoaderRegistry::empty();
+        for scheme in schemes {
+            let resolved = resolved.clone();
+            registry.register(scheme, move |source| {
+                resolved.lock().unwrap().push(source.to_string());
+                Ok(Box::new(TextFileLoader::new(source.to_string())))
+            });
+        }
+
+        (registry, resolved)
+    }
+
+    #[test]
+    fn resolve_test() {
+        let (mut registry, resolved) = recording_registry(&["yt", "yt-playlist", "file", "dir"]);
+        let custom = Arc::new(Mutex::new(vec![]));
+        let custom_sources = custom.clone();
+        registry.register("Notes", move |source| {
+            custom_sources.lock().unwrap().push(source.to_string());
+            Ok(Box::new(TextFileLoader::new(source.to_string())))
+        });
+
+        let dir = std::env::temp_dir();
+        let dir = dir.to_string_lossy();
+        for source in [
+            "https://youtu.be/abc123?t=42",
+            "https://www.youtube.com/watch?v=abc123&list=PL1",
+            "https://m.youtube.com/shorts/abc123",
+            "http://youtube.com/embed/abc123",
+            "https://www.youtube.com/playlist?list=PL1",
+            "yt://abc123",
+            " /no/such/notes.md ",
+            &dir,
+            "notes://today",
+            "NOTES://tomorrow",
+        ] {
+            assert!(registry.resolve(source).is_ok(), "{source}");
+        }
+
+        assert_eq!(
+            *resolved.lock().unwrap(),
+            vec![
+                "yt://abc123".to_string(),
+                "yt://abc123".to_string(),
+                "yt://abc123".to_string(),
+                "yt://abc123".to_string(),
+                "yt-playlist://PL1".to_string(),
+                "yt://abc123".to_string(),
+                "file:///no/such/notes.md".to_string(),
+                format!("dir://{dir}"),
+            ]
+        );
+        assert_eq!(
+            *custom.lock().unwrap(),
+            vec!["notes://today", "NOTES://tomorrow"]
+        );
+
+        for source in ["ftp://example.com/file.txt", "https://example.com/page"] {
+            assert!(matches!(
+                registry.resolve(source),
+                Err(LoaderError::UnsupportedSource(s)) if s == source
+            ));
+        }
+        assert_eq!(
+            youtube_source("https://www.youtube.com/feed/trending"),
+            None
+        );
+        assert!(file_loader_for_path(Path::new("/tmp/program.exe")).is_none());
+        assert!(LoaderRegistry::new().schemes().contains(&"repo"));
+    }
+}
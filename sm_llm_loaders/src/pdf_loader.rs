@@ -2,7 +2,8 @@ use std::path::PathBuf;
 
 use async_trait::async_trait;
 
-use crate::{Document, DocumentLoader, LoaderError};
+use crate::incremental::file_revision;
+use crate::{stamp_document, Document, DocumentLoader, LoaderError};
 
 pub struct NaivePdfFileLoader {
     pub path: PathBuf,
@@ -19,20 +20,40 @@ type PdfFileLoaderMetadata = Vec<(String, String)>;
 #[async_trait]
 impl DocumentLoader<PdfFileLoaderMetadata> for NaivePdfFileLoader {
     async fn load(&self) -> Result<Vec<Document<PdfFileLoaderMetadata>>, LoaderError> {
-        let bytes = std::fs::read(self.path.clone())?;
-        let content = pdf_extract::extract_text_from_mem(&bytes)
-            .map_err(|e| LoaderError::SourceReadError(e.to_string()))?;
+        let revision = file_revision(&self.path).await?;
+        let bytes = tokio::fs::read(&self.path).await?;
 
-        let metadata = vec![(
-            "source_file".to_string(),
-            self.path.to_string_lossy().to_string(),
-        )];
+        let source_file = self.path.to_string_lossy().to_string();
 
-        let doc = Document {
-            page_content: content,
-            metadata: Some(metadata),
-        };
+        // Text extraction is CPU-bound, keep it off the async workers
+        let mut docs = tokio::task::spawn_blocking(move || parse_pdf(&bytes, &source_file))
+            .await
+            .map_err(|e| LoaderError::SourceReadError(e.to_string()))??;
 
-        Ok(vec![doc])
+        for doc in docs.iter_mut() {
+            stamp_document(doc, Some(&revision));
+        }
+
+        Ok(docs)
+    }
+
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        file_revision(&self.path).await.map(Some)
     }
 }
+
+/// Extracts the text of a PDF held in memory. `source_file` only ends up
+/// in the metadata.
+pub(crate) fn parse_pdf(
+    bytes: &[u8],
+    source_file: &str,
+) -> Result<Vec<Document<PdfFileLoaderMetadata>>, LoaderError> {
+    let content = pdf_extract::extract_text_from_mem(bytes)
+        .map_err(|e| LoaderError::SourceReadError(e.to_string()))?;
+    let metadata = vec![("source_file".to_string(), source_file.to_string())];
+
+    Ok(vec![Document {
+        page_content: content,
+        metadata: Some(metadata),
+    }])
+}
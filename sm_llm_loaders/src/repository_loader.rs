@@ -0,0 +1,455 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+
+use crate::incremental::modified_revision;
+use crate::{content_hash, stamp_document, Document, DocumentLoader, LoaderError, SourceMetadata};
+
+/// Files larger than this are most likely generated or data files.
+const MAX_SOURCE_FILE_SIZE: u64 = 1024 * 1024;
+
+/// (extension, language) pairs of the files we load.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("jsx", "javascript"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("go", "go"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("hpp", "cpp"),
+    ("cs", "csharp"),
+    ("rb", "ruby"),
+    ("php", "php"),
+    ("swift", "swift"),
+    ("sh", "shell"),
+    ("toml", "toml"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("json", "json"),
+    ("md", "markdown"),
+];
+
+/// Language of a file from its extension.
+pub fn language_for_path(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+
+    LANGUAGES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, language)| *language)
+}
+
+/// Loads the source files of a git working tree, skipping whatever
+/// `.gitignore` (and `.ignore`, `.git/info/exclude`) excludes as well as
+/// hidden files.
+///
+/// Every document records the `path` relative to the repository root,
+/// the `language`, the `start_line`/`end_line` it spans (1-based,
+/// inclusive) and the `commit` checked out, when there is one.
+///
+/// The source revision is the commit checked out plus a hash of the
+/// path, size and modification time of the files loaded, so that
+/// uncommitted changes count as a new revision too.
+///
+/// By default a document is a whole file. With `with_item_split` files
+/// of languages we know the item syntax of (Rust, Python, JS/TS, Go,
+/// Ruby) are cut into one document per top-level item (function, class,
+/// struct, ...) along with the comments, attributes and decorators right
+/// above it, the code before the first item forming a document of its
+/// own. The split looks at lines starting in column 0 only, it doesn't
+/// parse the code.
+#[derive(Clone)]
+pub struct RepositoryLoader {
+    pub path: PathBuf,
+    pub split_items: bool,
+}
+
+impl RepositoryLoader {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            split_items: false,
+        }
+    }
+
+    pub fn with_item_split(mut self, split_items: bool) -> Self {
+        self.split_items = split_items;
+        self
+    }
+
+    /// Lists the source files this loader would visit, sorted by path.
+    pub fn list_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = ignore::WalkBuilder::new(&self.path)
+            .require_git(false)
+            .build()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().map(|t| t.is_file()).unwrap_or(false))
+            .map(|entry| entry.into_path())
+            .filter(|path| language_for_path(path).is_some())
+            .collect();
+        files.sort();
+
+        files
+    }
+
+    /// Revision of the working tree with `files`, see `RepositoryLoader`.
+    fn working_tree_revision(&self, files: &[PathBuf]) -> Result<String, LoaderError> {
+        let mut state = String::new();
+        for file in files {
+            let metadata = fs::metadata(file)?;
+            state.push_str(&format!(
+                "{}\t{}\t{}\n",
+                file.strip_prefix(&self.path).unwrap_or(file).display(),
+                metadata.len(),
+                modified_revision(&metadata)?
+            ));
+        }
+        let dirty_hash = &content_hash(&state)[..16];
+
+        Ok(match head_commit(&self.path) {
+            Some(commit) => format!("{commit}+{dirty_hash}"),
+            None => format!("worktree:{dirty_hash}"),
+        })
+    }
+
+    fn load_blocking(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let commit = head_commit(&self.path);
+        let files = self.list_files();
+        let revision = self.working_tree_revision(&files)?;
+
+        let mut docs = vec![];
+        for file in files {
+            if fs::metadata(&file)?.len() > MAX_SOURCE_FILE_SIZE {
+                continue;
+            }
+            // Binary or otherwise non UTF-8 files are skipped
+            let Ok(content) = fs::read_to_string(&file) else {
+                continue;
+            };
+            let language = language_for_path(&file).unwrap_or_default();
+            let relative_path = file
+                .strip_prefix(&self.path)
+                .unwrap_or(&file)
+                .to_string_lossy()
+                .to_string();
+
+            let lines: Vec<&str> = content.lines().collect();
+            let chunks = if self.split_items {
+                split_items(&lines, language)
+            } else {
+                vec![(0, lines.len())]
+            };
+
+            for (start, end) in chunks {
+                let page_content = lines[start..end].join("\n");
+                if page_content.trim().is_empty() {
+                    continue;
+                }
+
+                let mut metadata = vec![
+                    ("path".to_string(), relative_path.clone()),
+                    ("language".to_string(), language.to_string()),
+                    ("start_line".to_string(), (start + 1).to_string()),
+                    ("end_line".to_string(), end.to_string()),
+                ];
+                if let Some(commit) = &commit {
+                    metadata.push(("commit".to_string(), commit.clone()));
+                }
+
+                let mut doc = Document {
+                    page_content,
+                    metadata: Some(metadata),
+                };
+                stamp_document(&mut doc, Some(&revision));
+                docs.push(doc);
+            }
+        }
+
+        Ok(docs)
+    }
+}
+
+#[async_trait]
+impl DocumentLoader<SourceMetadata> for RepositoryLoader {
+    async fn load(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let loader = self.clone();
+
+        tokio::task::spawn_blocking(move || loader.load_blocking())
+            .await
+            .map_err(|e| LoaderError::SourceReadError(e.to_string()))?
+    }
+
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        let loader = self.clone();
+
+        tokio::task::spawn_blocking(move || {
+            loader.working_tree_revision(&loader.list_files()).map(Some)
+        })
+        .await
+        .map_err(|e| LoaderError::SourceReadError(e.to_string()))?
+    }
+}
+
+/// Hash of the commit checked out in the repository at `repo_path`, read
+/// straight from `.git` so no git binary is needed.
+pub fn head_commit(repo_path: &Path) -> Option<String> {
+    let git_dir = git_dir(repo_path)?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+
+    let Some(reference) = head.strip_prefix("ref:") else {
+        // Detached HEAD
+        return Some(head.to_string());
+    };
+    let reference = reference.trim();
+
+    if let Ok(commit) = fs::read_to_string(git_dir.join(reference)) {
+        return Some(commit.trim().to_string());
+    }
+
+    // The reference may only be in packed-refs, which worktrees share
+    // with the main repository
+    let common_dir = fs::read_to_string(git_dir.join("commondir"))
+        .map(|dir| git_dir.join(dir.trim()))
+        .unwrap_or(git_dir);
+    let packed_refs = fs::read_to_string(common_dir.join("packed-refs")).ok()?;
+    packed_refs
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+        .find_map(|line| {
+            let (commit, name) = line.split_once(' ')?;
+            (name.trim() == reference).then(|| commit.to_string())
+        })
+}
+
+/// The `.git` directory, following the `gitdir:` file of worktrees and
+/// submodules.
+fn git_dir(repo_path: &Path) -> Option<PathBuf> {
+    let dot_git = repo_path.join(".git");
+
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+
+    let gitdir = fs::read_to_string(&dot_git).ok()?;
+    let gitdir = gitdir.trim().strip_prefix("gitdir:")?.trim();
+    Some(repo_path.join(gitdir))
+}
+
+/// Cuts `lines` into `[start, end)` line ranges, one per top-level item.
+/// Languages without item rules come back as a single range.
+fn split_items(lines: &[&str], language: &str) -> Vec<(usize, usize)> {
+    let Some(is_item) = item_rule(language) else {
+        return vec![(0, lines.len())];
+    };
+
+    let mut starts = vec![];
+    // First line of the comments/attributes run above the current line
+    let mut leading_start = None;
+    for (index, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            leading_start = None;
+        } else if is_item(line) {
+            starts.push(leading_start.take().unwrap_or(index));
+        } else if is_leading_line(line, language) {
+            leading_start.get_or_insert(index);
+        } else {
+            leading_start = None;
+        }
+    }
+
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+
+    let mut ranges = vec![];
+    for (i, start) in starts.iter().enumerate() {
+        let mut end = starts.get(i + 1).copied().unwrap_or(lines.len());
+        while end > *start && lines[end - 1].trim().is_empty() {
+            end -= 1;
+        }
+        if end > *start {
+            ranges.push((*start, end));
+        }
+    }
+
+    ranges
+}
+
+/// Comments, attributes and decorators that belong to the item below.
+fn is_leading_line(line: &str, language: &str) -> bool {
+    match language {
+        "python" => line.starts_with('@') || line.starts_with('#'),
+        "ruby" => line.starts_with('#'),
+        "rust" => line.starts_with("//") || line.starts_with("#["),
+        _ => {
+            line.starts_with("//")
+                || line.starts_with("/*")
+                || line.starts_with(" *")
+                || line.starts_with('@')
+        }
+    }
+}
+
+fn item_rule(language: &str) -> Option<fn(&str) -> bool> {
+    match language {
+        "rust" => Some(is_rust_item),
+        "python" => Some(|line| starts_with_keyword(line, &["def", "class", "async def"])),
+        "javascript" | "typescript" => Some(is_js_item),
+        "go" => Some(|line| starts_with_keyword(line, &["func", "type", "var", "const"])),
+        "ruby" => Some(|line| starts_with_keyword(line, &["def", "class", "module"])),
+        _ => None,
+    }
+}
+
+fn is_rust_item(line: &str) -> bool {
+    let mut rest = line;
+    if let Some(after_pub) = rest.strip_prefix("pub") {
+        rest = match after_pub.strip_prefix('(') {
+            Some(restricted) => restricted.split_once(')').map(|(_, r)| r).unwrap_or(""),
+            None => after_pub,
+        };
+        rest = rest.trim_start();
+    }
+    for qualifier in ["async ", "unsafe ", "extern \"C\" "] {
+        rest = rest.strip_prefix(qualifier).unwrap_or(rest);
+    }
+
+    rest.starts_with("macro_rules!")
+        || starts_with_keyword(
+            rest,
+            &[
+                "fn", "struct", "enum", "union", "trait", "impl", "mod", "const", "static", "type",
+            ],
+        )
+}
+
+fn is_js_item(line: &str) -> bool {
+    let mut rest = line;
+    for prefix in ["export ", "default ", "declare ", "abstract ", "async "] {
+        rest = rest.strip_prefix(prefix).unwrap_or(rest);
+    }
+
+    starts_with_keyword(
+        rest,
+        &[
+            "function",
+            "function*",
+            "class",
+            "interface",
+            "type",
+            "enum",
+            "const",
+            "let",
+            "var",
+        ],
+    )
+}
+
+/// Whether `line` starts with one of `keywords` as a whole word.
+fn starts_with_keyword(line: &str, keywords: &[&str]) -> bool {
+    keywords.iter().any(|keyword| {
+        line.strip_prefix(keyword)
+            .and_then(|rest| rest.chars().next())
+            .map(|c| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(false)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata_value<'a>(doc: &'a Document<SourceMetadata>, key: &str) -> Option<&'a str> {
+        doc.metadata
+            .as_ref()?
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[tokio::test]
+    async fn load_test() {
+        let dir = std::env::temp_dir().join(format!("sm_llm_loaders_repo_{}", std::process::id()));
+        let git_dir = dir.join(".git");
+        fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(git_dir.join("refs/heads/main"), "1111\n").unwrap();
+        fs::write(dir.join("src/lib.rs"), "fn a() {}\n").unwrap();
+        fs::write(dir.join("notes.bin"), "skipped").unwrap();
+
+        assert_eq!(head_commit(&dir).as_deref(), Some("1111"));
+
+        let loader = RepositoryLoader::new(&dir);
+        let revision = loader.source_revision().await.unwrap().unwrap();
+        assert!(revision.starts_with("1111+"), "{revision}");
+
+        let docs = loader.load().await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].page_content, "fn a() {}");
+        assert_eq!(metadata_value(&docs[0], "path"), Some("src/lib.rs"));
+        assert_eq!(metadata_value(&docs[0], "commit"), Some("1111"));
+        assert_eq!(
+            metadata_value(&docs[0], "source_revision"),
+            Some(revision.as_str())
+        );
+
+        // Uncommitted changes are a new revision of the same commit
+        fs::write(dir.join("src/lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+        let dirty = loader.source_revision().await.unwrap().unwrap();
+        assert!(dirty.starts_with("1111+") && dirty != revision, "{dirty}");
+
+        // References packed in packed-refs, and detached heads
+        fs::remove_file(git_dir.join("refs/heads/main")).unwrap();
+        fs::write(
+            git_dir.join("packed-refs"),
+            "# pack-refs with: peeled\n2222 refs/heads/main\n^3333\n",
+        )
+        .unwrap();
+        assert_eq!(head_commit(&dir).as_deref(), Some("2222"));
+        fs::write(git_dir.join("HEAD"), "4444\n").unwrap();
+        assert_eq!(head_commit(&dir).as_deref(), Some("4444"));
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(head_commit(&dir), None);
+    }
+
+    #[test]
+    fn split_items_test() {
+        let source = r#"use std::fmt;
+
+/// Docs
+#[derive(Debug)]
+pub struct Foo {
+    x: i32,
+}
+
+impl Foo {
+    fn bar() {}
+}
+
+fn baz() {}
+"#;
+        let lines: Vec<&str> = source.lines().collect();
+
+        assert_eq!(
+            split_items(&lines, "rust"),
+            vec![(0, 1), (2, 7), (8, 11), (12, 13)]
+        );
+        assert_eq!(split_items(&lines, "toml"), vec![(0, lines.len())]);
+
+        let source = "import os\n\n@cache\ndef f():\n    pass\n\nclass A:\n    x = 1\n";
+        let lines: Vec<&str> = source.lines().collect();
+        assert_eq!(split_items(&lines, "python"), vec![(0, 1), (2, 5), (6, 8)]);
+    }
+}
@@ -0,0 +1,231 @@
+use std::ops::Range;
+
+use crate::{CleanedCaptions, SourceMetadata, TimedSegment};
+
+/// Consecutive timed segments merged into a paragraph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedParagraph {
+    pub text: String,
+    pub start: f64,
+    pub end: f64,
+    /// Indices of the merged segments
+    pub segment_range: Range<usize>,
+}
+
+impl TimedParagraph {
+    /// `start_seconds`, `end_seconds` and `segment_start`/`segment_end`
+    /// (end exclusive) metadata entries.
+    pub fn metadata(&self) -> SourceMetadata {
+        vec![
+            ("start_seconds".to_string(), format!("{:.2}", self.start)),
+            ("end_seconds".to_string(), format!("{:.2}", self.end)),
+            (
+                "segment_start".to_string(),
+                self.segment_range.start.to_string(),
+            ),
+            (
+                "segment_end".to_string(),
+                self.segment_range.end.to_string(),
+            ),
+        ]
+    }
+}
+
+/// Merges short caption segments into paragraphs.
+///
+/// A new paragraph starts:
+/// - after a pause of at least `pause_threshold` seconds,
+/// - after a sentence end followed by a pause of at least
+///   `sentence_pause` seconds, or once the paragraph is
+///   `target_duration` seconds long,
+/// - before the paragraph would get longer than `max_duration` seconds
+///   or `max_chars` characters.
+#[derive(Debug, Clone)]
+pub struct SegmentMerger {
+    pub pause_threshold: f64,
+    pub sentence_pause: f64,
+    pub target_duration: f64,
+    pub max_duration: f64,
+    pub max_chars: usize,
+}
+
+impl SegmentMerger {
+    pub fn new() -> Self {
+        Self {
+            pause_threshold: 2.0,
+            sentence_pause: 0.5,
+            target_duration: 30.0,
+            max_duration: 90.0,
+            max_chars: 1500,
+        }
+    }
+
+    pub fn with_pause_threshold(mut self, seconds: f64) -> Self {
+        self.pause_threshold = seconds;
+        self
+    }
+
+    pub fn with_sentence_pause(mut self, seconds: f64) -> Self {
+        self.sentence_pause = seconds;
+        self
+    }
+
+    pub fn with_target_duration(mut self, seconds: f64) -> Self {
+        self.target_duration = seconds;
+        self
+    }
+
+    pub fn with_max_duration(mut self, seconds: f64) -> Self {
+        self.max_duration = seconds;
+        self
+    }
+
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+
+    pub fn merge(&self, segments: &[TimedSegment]) -> Vec<TimedParagraph> {
+        self.merge_with_breaks(segments, &[])
+    }
+
+    /// Merges the sentences of cleaned captions, speaker turns always
+    /// starting a new paragraph.
+    pub fn merge_cleaned(&self, cleaned: &CleanedCaptions) -> Vec<TimedParagraph> {
+        self.merge_with_breaks(&cleaned.sentences, &cleaned.turn_starts)
+    }
+
+    /// Like `merge`, also starting a new paragraph at every index in
+    /// `forced_breaks`.
+    pub fn merge_with_breaks(
+        &self,
+        segments: &[TimedSegment],
+        forced_breaks: &[usize],
+    ) -> Vec<TimedParagraph> {
+        let mut paragraphs = vec![];
+        let mut current: Option<TimedParagraph> = None;
+
+        for (index, segment) in segments.iter().enumerate() {
+            let text = segment.text.trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            if let Some(paragraph) = current.take() {
+                if forced_breaks.contains(&index) || self.breaks_before(&paragraph, segment) {
+                    paragraphs.push(paragraph);
+                } else {
+                    current = Some(paragraph);
+                }
+            }
+
+            match current.as_mut() {
+                Some(paragraph) => {
+                    paragraph.text.push(' ');
+                    paragraph.text.push_str(text);
+                    paragraph.end = paragraph.end.max(segment.end());
+                    paragraph.segment_range.end = index + 1;
+                }
+                None => {
+                    current = Some(TimedParagraph {
+                        text: text.to_string(),
+                        start: segment.start,
+                        end: segment.end(),
+                        segment_range: index..index + 1,
+                    })
+                }
+            }
+        }
+        paragraphs.extend(current);
+
+        paragraphs
+    }
+
+    fn breaks_before(&self, paragraph: &TimedParagraph, next: &TimedSegment) -> bool {
+        let pause = next.start - paragraph.end;
+        if pause >= self.pause_threshold {
+            return true;
+        }
+
+        let sentence_end = paragraph.text.ends_with(['.', '?', '!', '…']);
+        if sentence_end
+            && (pause >= self.sentence_pause
+                || paragraph.end - paragraph.start >= self.target_duration)
+        {
+            return true;
+        }
+
+        next.end() - paragraph.start > self.max_duration
+            || paragraph.text.chars().count() + 1 + next.text.trim().chars().count()
+                > self.max_chars
+    }
+}
+
+impl Default for SegmentMerger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str, start: f64, duration: f64) -> TimedSegment {
+        TimedSegment {
+            text: text.to_string(),
+            start,
+            duration,
+        }
+    }
+
+    #[test]
+    fn merge_test() {
+        let segments = vec![
+            segment("Today we look", 0.0, 2.0),
+            segment("at tokenizers.", 2.0, 2.0),
+            segment("They split text", 4.8, 2.0),
+            segment("into pieces", 6.8, 2.0),
+            segment("", 8.8, 1.0),
+            segment("after a long pause", 12.0, 2.0),
+        ];
+
+        let paragraphs = SegmentMerger::new().merge(&segments);
+
+        assert_eq!(
+            paragraphs,
+            vec![
+                TimedParagraph {
+                    text: "Today we look at tokenizers.".to_string(),
+                    start: 0.0,
+                    end: 4.0,
+                    segment_range: 0..2,
+                },
+                TimedParagraph {
+                    text: "They split text into pieces".to_string(),
+                    start: 4.8,
+                    end: 8.8,
+                    segment_range: 2..4,
+                },
+                TimedParagraph {
+                    text: "after a long pause".to_string(),
+                    start: 12.0,
+                    end: 14.0,
+                    segment_range: 5..6,
+                },
+            ]
+        );
+
+        let paragraphs = SegmentMerger::new().with_max_chars(20).merge(&segments);
+        assert_eq!(paragraphs[0].text, "Today we look");
+
+        // Characters, not bytes: 24 characters but 45 bytes
+        let greek = vec![
+            segment("Σήμερα μιλάμε", 0.0, 2.0),
+            segment("για λέξεις", 2.0, 2.0),
+        ];
+        let paragraphs = SegmentMerger::new().with_max_chars(24).merge(&greek);
+        assert_eq!(paragraphs.len(), 1);
+        assert_eq!(paragraphs[0].text, "Σήμερα μιλάμε για λέξεις");
+    }
+}
@@ -0,0 +1,456 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use crate::incremental::{file_revision, modified_revision};
+use crate::{stamp_document, Document, DocumentLoader, LoaderError, SourceMetadata};
+
+/// Number of records per batch when loading everything at once.
+const DEFAULT_BATCH_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TabularFormat {
+    Csv,
+    Tsv,
+    JsonLines,
+}
+
+impl TabularFormat {
+    /// Guesses the format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_string_lossy().to_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "tsv" | "tab" => Some(Self::Tsv),
+            "jsonl" | "ndjson" => Some(Self::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Field(String),
+}
+
+/// Template building the `page_content` of a record, e.g.
+/// `"Q: {question}\nA: {answer}"`.
+///
+/// Fields are column names for CSV/TSV. For JSONL they are JSON pointers
+/// (`{/ticket/title}`), a plain `{name}` being short for `{/name}`.
+/// `{{` and `}}` produce literal braces. Missing fields render empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl ContentTemplate {
+    pub fn parse(template: &str) -> Result<Self, LoaderError> {
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    let mut terminated = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            terminated = true;
+                            break;
+                        }
+                        field.push(c);
+                    }
+                    if !terminated || field.trim().is_empty() {
+                        return Err(LoaderError::SourceReadError(format!(
+                            "Empty or unterminated field in template: {template}"
+                        )));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TemplatePart::Field(field.trim().to_string()));
+                }
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+
+        Ok(Self { parts })
+    }
+
+    fn render(&self, record: &Record) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Literal(literal) => literal.clone(),
+                TemplatePart::Field(field) => record.get(field).unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+/// A single row of a CSV/TSV file or line of a JSONL file.
+enum Record<'a> {
+    Row {
+        headers: &'a csv::StringRecord,
+        values: csv::StringRecord,
+    },
+    Json(serde_json::Value),
+}
+
+impl Record<'_> {
+    fn get(&self, field: &str) -> Option<String> {
+        match self {
+            Record::Row { headers, values } => headers
+                .iter()
+                .position(|h| h == field)
+                .and_then(|i| values.get(i))
+                .map(String::from),
+            Record::Json(value) => {
+                let pointer = if field.starts_with('/') {
+                    field.to_string()
+                } else {
+                    format!("/{field}")
+                };
+                value.pointer(&pointer).map(json_to_string)
+            }
+        }
+    }
+
+    /// `name: value` lines for every field, used without a template.
+    fn render_all(&self) -> String {
+        let fields: Vec<(String, String)> = match self {
+            Record::Row { headers, values } => headers
+                .iter()
+                .zip(values.iter())
+                .map(|(h, v)| (h.to_string(), v.to_string()))
+                .collect(),
+            Record::Json(serde_json::Value::Object(map)) => map
+                .iter()
+                .map(|(k, v)| (k.clone(), json_to_string(v)))
+                .collect(),
+            Record::Json(value) => vec![("value".to_string(), json_to_string(value))],
+        };
+
+        fields
+            .into_iter()
+            .filter(|(_, v)| !v.is_empty())
+            .map(|(k, v)| format!("{k}: {v}"))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+fn json_to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Batches of documents streamed by `TabularLoader::stream`.
+pub type DocumentBatches = mpsc::Receiver<Result<Vec<Document<SourceMetadata>>, LoaderError>>;
+
+/// Loads CSV, TSV and JSONL files, one document per record.
+///
+/// The `page_content` comes from a `ContentTemplate`, or lists all fields
+/// as `name: value` lines when there is none. Extra fields can be mapped
+/// into the metadata with `with_metadata_field`. Records rendering to
+/// empty content are skipped.
+///
+/// Files are read in a blocking task and handed out in batches, so
+/// `stream` can be used for files that don't fit in memory.
+#[derive(Clone)]
+pub struct TabularLoader {
+    pub path: PathBuf,
+    pub format: TabularFormat,
+    pub template: Option<ContentTemplate>,
+    /// (metadata key, field) pairs
+    pub metadata_fields: Vec<(String, String)>,
+}
+
+impl TabularLoader {
+    pub fn new<T: Into<PathBuf>>(path: T, format: TabularFormat) -> Self {
+        Self {
+            path: path.into(),
+            format,
+            template: None,
+            metadata_fields: vec![],
+        }
+    }
+
+    pub fn with_template(mut self, template: &str) -> Result<Self, LoaderError> {
+        self.template = Some(ContentTemplate::parse(template)?);
+        Ok(self)
+    }
+
+    pub fn with_metadata_field(mut self, key: &str, field: &str) -> Self {
+        self.metadata_fields
+            .push((key.to_string(), field.to_string()));
+        self
+    }
+
+    /// Streams the documents in batches of at most `batch_size`, read
+    /// in a blocking task of the current tokio runtime. Fails when not
+    /// called from within a tokio runtime.
+    pub fn stream(&self, batch_size: usize) -> Result<DocumentBatches, LoaderError> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .map_err(|e| LoaderError::SourceReadError(format!("Can't stream records: {e}")))?;
+        let (sender, receiver) = mpsc::channel(2);
+        let loader = self.clone();
+        let batch_size = batch_size.max(1);
+
+        runtime.spawn_blocking(move || {
+            if let Err(e) = loader.read_batches(batch_size, &sender) {
+                let _ = sender.blocking_send(Err(e));
+            }
+        });
+
+        Ok(receiver)
+    }
+
+    fn read_batches(
+        &self,
+        batch_size: usize,
+        sender: &mpsc::Sender<Result<Vec<Document<SourceMetadata>>, LoaderError>>,
+    ) -> Result<(), LoaderError> {
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut emit = |doc: Option<Document<SourceMetadata>>, flush: bool| -> bool {
+            batch.extend(doc);
+            if batch.len() >= batch_size || (flush && !batch.is_empty()) {
+                // A closed channel means the receiver is gone, stop reading
+                return sender.blocking_send(Ok(std::mem::take(&mut batch))).is_ok();
+            }
+            true
+        };
+
+        let file = File::open(&self.path)?;
+        let revision = modified_revision(&file.metadata()?)?;
+
+        self.read_records(file, Some(&revision), &mut emit)
+    }
+
+    /// Parses records from `reader`, handing each document, stamped with
+    /// `revision`, to `emit` until it returns `false`. `emit` is called one
+    /// last time with `None` and `flush` set once the input is exhausted.
+    fn read_records<R: Read>(
+        &self,
+        reader: R,
+        revision: Option<&str>,
+        emit: &mut dyn FnMut(Option<Document<SourceMetadata>>, bool) -> bool,
+    ) -> Result<(), LoaderError> {
+        match self.format {
+            TabularFormat::Csv | TabularFormat::Tsv => {
+                let delimiter = if self.format == TabularFormat::Tsv {
+                    b'\t'
+                } else {
+                    b','
+                };
+                let mut reader = csv::ReaderBuilder::new()
+                    .delimiter(delimiter)
+                    // TSV has no quoting, quotes are part of the values
+                    .quoting(self.format != TabularFormat::Tsv)
+                    .flexible(true)
+                    .from_reader(reader);
+                let headers = reader.headers().map_err(csv_error)?.clone();
+
+                for (row, values) in reader.into_records().enumerate() {
+                    let record = Record::Row {
+                        headers: &headers,
+                        values: values.map_err(csv_error)?,
+                    };
+                    if !emit(self.to_document(row, &record, revision), false) {
+                        return Ok(());
+                    }
+                }
+            }
+            TabularFormat::JsonLines => {
+                for (row, line) in BufReader::new(reader).lines().enumerate() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let value = serde_json::from_str(&line).map_err(|e| {
+                        LoaderError::SourceReadError(format!(
+                            "Invalid JSON on line {}: {e}",
+                            row + 1
+                        ))
+                    })?;
+                    if !emit(self.to_document(row, &Record::Json(value), revision), false) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        emit(None, true);
+
+        Ok(())
+    }
+
+    /// Parses a file held in memory with the default settings.
+    /// `source_file` only ends up in the metadata.
+    pub(crate) fn parse_bytes(
+        bytes: &[u8],
+        format: TabularFormat,
+        source_file: &str,
+    ) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let loader = Self::new(source_file, format);
+
+        let mut docs = vec![];
+        loader.read_records(bytes, None, &mut |doc, _| {
+            docs.extend(doc);
+            true
+        })?;
+
+        Ok(docs)
+    }
+
+    fn to_document(
+        &self,
+        row: usize,
+        record: &Record,
+        revision: Option<&str>,
+    ) -> Option<Document<SourceMetadata>> {
+        let content = match &self.template {
+            Some(template) => template.render(record),
+            None => record.render_all(),
+        };
+        if content.trim().is_empty() {
+            return None;
+        }
+
+        let mut metadata = vec![
+            (
+                "source_file".to_string(),
+                self.path.to_string_lossy().to_string(),
+            ),
+            ("row".to_string(), row.to_string()),
+        ];
+        for (key, field) in self.metadata_fields.iter() {
+            if let Some(value) = record.get(field) {
+                metadata.push((key.clone(), value));
+            }
+        }
+
+        let mut doc = Document {
+            page_content: content,
+            metadata: Some(metadata),
+        };
+        stamp_document(&mut doc, revision);
+
+        Some(doc)
+    }
+}
+
+fn csv_error(e: csv::Error) -> LoaderError {
+    LoaderError::SourceReadError(format!("CSV error: {e}"))
+}
+
+#[async_trait]
+impl DocumentLoader<SourceMetadata> for TabularLoader {
+    async fn load(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let mut receiver = self.stream(DEFAULT_BATCH_SIZE)?;
+
+        let mut docs = vec![];
+        while let Some(batch) = receiver.recv().await {
+            docs.extend(batch?);
+        }
+
+        Ok(docs)
+    }
+
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        file_revision(&self.path).await.map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_parse_test() {
+        let template = ContentTemplate::parse("Q: {question}\nA: { /answer/text } {{x}}").unwrap();
+
+        assert_eq!(
+            template.parts,
+            vec![
+                TemplatePart::Literal("Q: ".to_string()),
+                TemplatePart::Field("question".to_string()),
+                TemplatePart::Literal("\nA: ".to_string()),
+                TemplatePart::Field("/answer/text".to_string()),
+                TemplatePart::Literal(" {x}".to_string()),
+            ]
+        );
+        assert!(ContentTemplate::parse("broken {").is_err());
+        assert!(ContentTemplate::parse("broken {x").is_err());
+        assert!(ContentTemplate::parse("empty {  } field").is_err());
+    }
+
+    #[test]
+    fn template_render_json_test() {
+        let template = ContentTemplate::parse("{title}: {/body/text} ({missing})").unwrap();
+        let record = Record::Json(serde_json::json!({
+            "title": "Login fails",
+            "body": { "text": "After the update" },
+        }));
+
+        assert_eq!(template.render(&record), "Login fails: After the update ()");
+    }
+
+    #[tokio::test]
+    async fn load_revision_test() {
+        let dir = std::env::temp_dir().join(format!("tabular_loader_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tickets.csv");
+        std::fs::write(&path, "title,body\nLogin fails,After the update\n").unwrap();
+
+        let loader = TabularLoader::new(&path, TabularFormat::Csv);
+        let docs = loader.load().await.unwrap();
+        let revision = loader.source_revision().await.unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(docs.len(), 1);
+        assert_eq!(
+            docs[0].page_content,
+            "title: Login fails\nbody: After the update"
+        );
+        let metadata = docs[0].metadata.as_ref().unwrap();
+        assert!(metadata.contains(&("source_revision".to_string(), revision)));
+    }
+
+    #[test]
+    fn parse_tsv_test() {
+        let docs = TabularLoader::parse_bytes(
+            b"title\tbody\n\"Quoted\" title\tsays \"hi\n",
+            TabularFormat::Tsv,
+            "tickets.tsv",
+        )
+        .unwrap();
+
+        assert_eq!(docs.len(), 1);
+        assert_eq!(
+            docs[0].page_content,
+            "title: \"Quoted\" title\nbody: says \"hi"
+        );
+        assert!(TabularLoader::new("tickets.tsv", TabularFormat::Tsv)
+            .stream(10)
+            .is_err());
+    }
+}
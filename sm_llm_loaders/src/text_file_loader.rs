@@ -1,10 +1,8 @@
 use async_trait::async_trait;
 use llm_chain::schema::Document;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
 
-use crate::{DocumentLoader, LoaderError};
+use crate::incremental::file_revision;
+use crate::{stamp_document, DocumentLoader, LoaderError};
 
 pub struct TextFileLoader {
     pub path: String,
@@ -20,31 +18,42 @@ type TextFileLoaderMetadata = Vec<(String, String)>;
 
 /// This is a DocumentLoader implementation for simple text
 /// files. Metadata added is the file source path and the
-/// filename.
+/// filename, plus the content hash and the file mtime as revision.
 /// Currently supports UTF-8 encoded text files only.
 /// Invalid UTF-8 characters in source file error out.
 #[async_trait]
 impl DocumentLoader<TextFileLoaderMetadata> for TextFileLoader {
     async fn load(&self) -> Result<Vec<Document<TextFileLoaderMetadata>>, LoaderError> {
-        let content = read_text_file(&self.path)?;
-        let metadata = vec![("source_file".to_string(), self.path.clone())];
+        let revision = file_revision(&self.path).await?;
+        let bytes = tokio::fs::read(&self.path).await?;
 
-        let doc = Document {
-            page_content: content,
-            metadata: Some(metadata),
-        };
+        let mut docs = parse_text(bytes, &self.path)?;
+        for doc in docs.iter_mut() {
+            stamp_document(doc, Some(&revision));
+        }
 
-        Ok(vec![doc])
+        Ok(docs)
     }
-}
-
-fn read_text_file<P: AsRef<Path>>(path: P) -> Result<String, LoaderError> {
-    let mut file = File::open(path)?;
-    let mut content = String::new();
 
-    file.read_to_string(&mut content)?;
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        file_revision(&self.path).await.map(Some)
+    }
+}
 
-    Ok(content)
+/// Parses a UTF-8 text file held in memory. `source_file` only ends up
+/// in the metadata.
+pub(crate) fn parse_text(
+    bytes: Vec<u8>,
+    source_file: &str,
+) -> Result<Vec<Document<TextFileLoaderMetadata>>, LoaderError> {
+    let content = String::from_utf8(bytes)
+        .map_err(|e| LoaderError::FileReadError(format!("{source_file}: {e}")))?;
+    let metadata = vec![("source_file".to_string(), source_file.to_string())];
+
+    Ok(vec![Document {
+        page_content: content,
+        metadata: Some(metadata),
+    }])
 }
 
 // @TODO: add some basic tests for TextFileLoader
@@ -11,6 +11,12 @@ pub enum LoaderError {
     FileReadError(String),
     #[error("Source read error: {0}")]
     SourceReadError(String),
+    #[error("Unsupported source: {0}")]
+    UnsupportedSource(String),
+    #[error("Manifest error: {0}")]
+    ManifestError(String),
+    #[error("Transform error: {0}")]
+    TransformError(String),
 }
 
 #[async_trait]
@@ -20,5 +26,19 @@ where
 {
     async fn load(&self) -> Result<Vec<Document<Metadata>>, LoaderError>;
 
+    /// A cheap marker (e.g. file mtime) that changes whenever the source
+    /// changes, computed without loading the source. Loaders that can only
+    /// tell after loading return `None` and record the revision in the
+    /// `source_revision` metadata of their documents instead.
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        Ok(None)
+    }
+
     // fn load_and_split(&self, text_splitter: Option<TextSplitter>) -> Vec<Document>;
 }
+
+/// Metadata shape shared by all the loaders of this crate.
+pub type SourceMetadata = Vec<(String, String)>;
+
+/// A type-erased loader, as handed out by the `LoaderRegistry`.
+pub type BoxedDocumentLoader = Box<dyn DocumentLoader<SourceMetadata> + Send + Sync>;
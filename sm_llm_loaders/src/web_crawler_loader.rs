@@ -0,0 +1,503 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use async_trait::async_trait;
+use url::Url;
+
+use crate::html_text::html_to_text;
+use crate::{stamp_document, Document, DocumentLoader, LoaderError, SourceMetadata};
+
+const DEFAULT_USER_AGENT: &str = "sm_llm_loaders";
+
+/// Crawls a website starting from seed URLs and loads every page as a
+/// document with its `source_url`, crawl `depth` and `title`.
+///
+/// Only links with the same origin as one of the seeds are followed, up
+/// to `max_depth` links away from a seed and `max_pages` pages overall.
+/// `robots.txt` is honoured and the URLs listed in the sitemaps of each
+/// origin (from `robots.txt`, or `/sitemap.xml`) are crawled as seeds.
+pub struct WebCrawlerLoader {
+    pub seeds: Vec<String>,
+    pub max_depth: usize,
+    pub max_pages: usize,
+    pub respect_robots_txt: bool,
+    pub use_sitemaps: bool,
+    pub user_agent: String,
+}
+
+impl WebCrawlerLoader {
+    pub fn new(seeds: Vec<String>) -> Self {
+        Self {
+            seeds,
+            max_depth: 2,
+            max_pages: 500,
+            respect_robots_txt: true,
+            use_sitemaps: true,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = max_pages;
+        self
+    }
+
+    pub fn with_robots_txt(mut self, respect_robots_txt: bool) -> Self {
+        self.respect_robots_txt = respect_robots_txt;
+        self
+    }
+
+    pub fn with_sitemaps(mut self, use_sitemaps: bool) -> Self {
+        self.use_sitemaps = use_sitemaps;
+        self
+    }
+
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl DocumentLoader<SourceMetadata> for WebCrawlerLoader {
+    async fn load(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let client = reqwest::Client::builder()
+            .user_agent(self.user_agent.clone())
+            .build()
+            .map_err(|e| LoaderError::SourceReadError(e.to_string()))?;
+
+        let mut crawl = Crawl {
+            loader: self,
+            client,
+            robots: HashMap::new(),
+            visited: HashSet::new(),
+            queue: VecDeque::new(),
+        };
+
+        let seeds = self
+            .seeds
+            .iter()
+            .map(|s| Url::parse(s).map_err(|e| LoaderError::UnsupportedSource(format!("{s}: {e}"))))
+            .collect::<Result<Vec<Url>, LoaderError>>()?;
+        let origins: HashSet<String> = seeds.iter().map(origin_of).collect();
+
+        for seed in seeds {
+            crawl.enqueue(seed, 0);
+        }
+        if self.use_sitemaps {
+            for origin in origins.iter() {
+                for url in crawl.sitemap_urls(origin).await {
+                    if origins.contains(&origin_of(&url)) {
+                        crawl.enqueue(url, 0);
+                    }
+                }
+            }
+        }
+
+        let mut docs = vec![];
+        while let Some((url, depth)) = crawl.queue.pop_front() {
+            if docs.len() >= self.max_pages {
+                break;
+            }
+            if !crawl.is_allowed(&url).await {
+                continue;
+            }
+
+            let Some(page) = crawl.fetch_page(&url).await else {
+                continue;
+            };
+
+            if depth < self.max_depth {
+                for link in page.links.iter() {
+                    if let Ok(mut link) = url.join(link) {
+                        link.set_fragment(None);
+                        if origins.contains(&origin_of(&link)) {
+                            crawl.enqueue(link, depth + 1);
+                        }
+                    }
+                }
+            }
+
+            if page.text.is_empty() {
+                continue;
+            }
+
+            let mut metadata = vec![
+                ("source_url".to_string(), url.to_string()),
+                ("depth".to_string(), depth.to_string()),
+            ];
+            if let Some(title) = page.title {
+                metadata.push(("title".to_string(), title));
+            }
+
+            let mut doc = Document {
+                page_content: page.text,
+                metadata: Some(metadata),
+            };
+            stamp_document(&mut doc, None);
+            docs.push(doc);
+        }
+
+        Ok(docs)
+    }
+}
+
+struct Page {
+    title: Option<String>,
+    text: String,
+    links: Vec<String>,
+}
+
+/// State of a single crawl.
+struct Crawl<'a> {
+    loader: &'a WebCrawlerLoader,
+    client: reqwest::Client,
+    /// robots.txt rules per origin
+    robots: HashMap<String, RobotsRules>,
+    visited: HashSet<String>,
+    queue: VecDeque<(Url, usize)>,
+}
+
+impl Crawl<'_> {
+    fn enqueue(&mut self, url: Url, depth: usize) {
+        if matches!(url.scheme(), "http" | "https") && self.visited.insert(url.to_string()) {
+            self.queue.push_back((url, depth));
+        }
+    }
+
+    async fn fetch_text(&self, url: &str) -> Option<(String, String)> {
+        let response = self.client.get(url).send().await.ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        Some((content_type, response.text().await.ok()?))
+    }
+
+    async fn fetch_page(&self, url: &Url) -> Option<Page> {
+        let (content_type, body) = self.fetch_text(url.as_str()).await?;
+        if !content_type.is_empty() && !content_type.contains("html") {
+            return None;
+        }
+
+        let extracted = html_to_text(&body);
+        Some(Page {
+            title: extracted.title,
+            text: extracted.text,
+            links: extracted.links,
+        })
+    }
+
+    async fn robots_rules(&mut self, origin: &str) -> &RobotsRules {
+        if !self.robots.contains_key(origin) {
+            let rules = match self.fetch_text(&format!("{origin}/robots.txt")).await {
+                Some((_, body)) => RobotsRules::parse(&body, &self.loader.user_agent),
+                None => RobotsRules::default(),
+            };
+            self.robots.insert(origin.to_string(), rules);
+        }
+
+        &self.robots[origin]
+    }
+
+    async fn is_allowed(&mut self, url: &Url) -> bool {
+        if !self.loader.respect_robots_txt {
+            return true;
+        }
+
+        let path = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        self.robots_rules(&origin_of(url)).await.is_allowed(&path)
+    }
+
+    /// URLs listed in the sitemaps of `origin`, following sitemap
+    /// indexes one level deep.
+    async fn sitemap_urls(&mut self, origin: &str) -> Vec<Url> {
+        let mut sitemaps = self.robots_rules(origin).await.sitemaps.clone();
+        if sitemaps.is_empty() {
+            sitemaps.push(format!("{origin}/sitemap.xml"));
+        }
+
+        let mut urls = vec![];
+        let mut nested = vec![];
+        for sitemap in sitemaps {
+            if let Some((_, body)) = self.fetch_text(&sitemap).await {
+                let (is_index, locations) = parse_sitemap(&body);
+                if is_index {
+                    nested.extend(locations);
+                } else {
+                    urls.extend(locations);
+                }
+            }
+        }
+        for sitemap in nested {
+            if let Some((_, body)) = self.fetch_text(&sitemap).await {
+                urls.extend(parse_sitemap(&body).1);
+            }
+        }
+
+        urls.iter().filter_map(|u| Url::parse(u).ok()).collect()
+    }
+}
+
+fn origin_of(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+/// Returns whether the document is a sitemap index and the `<loc>`
+/// entries it lists.
+fn parse_sitemap(xml: &str) -> (bool, Vec<String>) {
+    let Ok(doc) = roxmltree::Document::parse(xml) else {
+        return (false, vec![]);
+    };
+
+    let is_index = doc.root_element().tag_name().name() == "sitemapindex";
+    let locations = doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "loc")
+        .filter_map(|n| n.text())
+        .map(|t| t.trim().to_string())
+        .collect();
+
+    (is_index, locations)
+}
+
+/// The rules of a robots.txt that apply to one user agent.
+#[derive(Debug, Default)]
+struct RobotsRules {
+    /// (allow, path pattern)
+    rules: Vec<(bool, String)>,
+    sitemaps: Vec<String>,
+}
+
+impl RobotsRules {
+    /// Keeps the group naming `user_agent`, falling back to the `*` group.
+    fn parse(robots_txt: &str, user_agent: &str) -> Self {
+        let user_agent = user_agent.to_lowercase();
+
+        let mut sitemaps = vec![];
+        let mut specific_rules: Option<Vec<(bool, String)>> = None;
+        let mut wildcard_rules: Option<Vec<(bool, String)>> = None;
+
+        let mut group_agents: Vec<String> = vec![];
+        let mut group_rules: Vec<(bool, String)> = vec![];
+        let mut in_rules = false;
+
+        let mut close_group = |agents: &mut Vec<String>, rules: &mut Vec<(bool, String)>| {
+            if agents
+                .iter()
+                .any(|a| a != "*" && user_agent.contains(a.as_str()))
+            {
+                specific_rules.get_or_insert_with(Vec::new).append(rules);
+            } else if agents.iter().any(|a| a == "*") {
+                wildcard_rules.get_or_insert_with(Vec::new).append(rules);
+            }
+            agents.clear();
+            rules.clear();
+        };
+
+        for line in robots_txt.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        close_group(&mut group_agents, &mut group_rules);
+                        in_rules = false;
+                    }
+                    group_agents.push(value.to_lowercase());
+                }
+                "allow" | "disallow" => {
+                    in_rules = true;
+                    // An empty Disallow allows everything
+                    if !value.is_empty() {
+                        group_rules
+                            .push((key.trim().eq_ignore_ascii_case("allow"), value.to_string()));
+                    }
+                }
+                "sitemap" => sitemaps.push(value.to_string()),
+                _ => {}
+            }
+        }
+        close_group(&mut group_agents, &mut group_rules);
+
+        Self {
+            rules: specific_rules.or(wildcard_rules).unwrap_or_default(),
+            sitemaps,
+        }
+    }
+
+    /// The longest matching rule wins, `Allow` winning ties.
+    fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| robots_pattern_matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .map(|(allow, _)| *allow)
+            .unwrap_or(true)
+    }
+}
+
+/// Prefix match supporting the `*` wildcard and the `$` end anchor.
+fn robots_pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        let is_last = i == parts.len() - 1;
+        if is_last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    type Page = (&'static str, &'static str, String);
+
+    /// Serves the (path, content type, body) pages built by `pages` over
+    /// plain HTTP/1.1, returning the base URL.
+    async fn serve<F: FnOnce(&str) -> Vec<Page>>(pages: F) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let pages = pages(&base_url);
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let pages = pages.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+                    let response = match pages.iter().find(|(p, _, _)| *p == path) {
+                        Some((_, content_type, body)) => format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                            body.len()
+                        ),
+                        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string(),
+                    };
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        base_url
+    }
+
+    fn html(title: &str, body: &str) -> String {
+        format!("<html><head><title>{title}</title></head><body>{body}</body></html>")
+    }
+
+    #[tokio::test]
+    async fn crawl_local_site_test() {
+        let base_url = serve(|base_url| {
+            vec![
+                (
+                    "/",
+                    "text/html",
+                    html(
+                        "Home",
+                        r#"<p>Welcome</p><a href="/a#top">A</a><a href="/private/b">B</a>
+                        <a href="https://example.com/">External</a>"#,
+                    ),
+                ),
+                ("/a", "text/html", html("A", r#"<p>Page A</p><a href="c">C</a>"#)),
+                ("/c", "text/html", html("C", "<p>Too deep</p>")),
+                ("/private/b", "text/html", html("B", "<p>Disallowed</p>")),
+                ("/from-sitemap", "text/html", html("S", "<p>Listed</p>")),
+                (
+                    "/robots.txt",
+                    "text/plain",
+                    format!("User-agent: *\nDisallow: /private/\nSitemap: {base_url}/sitemap.xml\n"),
+                ),
+                (
+                    "/sitemap.xml",
+                    "application/xml",
+                    format!(
+                        r#"<?xml version="1.0"?><urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"><url><loc>{base_url}/from-sitemap</loc></url></urlset>"#
+                    ),
+                ),
+            ]
+        })
+        .await;
+
+        let loader = WebCrawlerLoader::new(vec![format!("{base_url}/")]).with_max_depth(1);
+        let docs = loader.load().await.unwrap();
+
+        let crawled: Vec<(String, String)> = docs
+            .iter()
+            .filter_map(|d| d.metadata.as_ref())
+            .map(|m| {
+                let value = |key: &str| {
+                    m.iter()
+                        .find(|(k, _)| k == key)
+                        .map(|(_, v)| v.clone())
+                        .unwrap_or_default()
+                };
+                (value("source_url"), value("depth"))
+            })
+            .collect();
+        assert_eq!(
+            crawled,
+            vec![
+                (format!("{base_url}/"), "0".to_string()),
+                (format!("{base_url}/from-sitemap"), "0".to_string()),
+                (format!("{base_url}/a"), "1".to_string()),
+            ]
+        );
+        assert_eq!(docs[2].page_content, "Page A\nC");
+    }
+
+    #[test]
+    fn robots_rules_test() {
+        let robots = "User-agent: otherbot\nDisallow: /\n\n\
+                      User-agent: *\nDisallow: /docs/\nAllow: /docs/public\nDisallow: /*.pdf$\n";
+        let rules = RobotsRules::parse(robots, DEFAULT_USER_AGENT);
+
+        assert!(rules.is_allowed("/"));
+        assert!(!rules.is_allowed("/docs/internal"));
+        assert!(rules.is_allowed("/docs/public/page"));
+        assert!(!rules.is_allowed("/files/manual.pdf"));
+        assert!(rules.is_allowed("/files/manual.pdf?download=1"));
+    }
+}
@@ -0,0 +1,87 @@
+use async_trait::async_trait;
+
+use crate::html_text::html_to_text;
+use crate::{stamp_document, Document, DocumentLoader, LoaderError, SourceMetadata};
+
+/// Loads a single web page over HTTP(S). HTML responses are converted
+/// to plain text, anything else is kept as-is.
+pub struct WebPageLoader {
+    pub url: String,
+}
+
+impl WebPageLoader {
+    pub fn new(url: String) -> Self {
+        Self { url }
+    }
+}
+
+/// Revision of a web page: its `ETag`, falling back to `Last-Modified`.
+fn response_revision(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+    header(reqwest::header::ETAG)
+        .map(|etag| format!("etag:{etag}"))
+        .or_else(|| header(reqwest::header::LAST_MODIFIED).map(|lm| format!("lastModified:{lm}")))
+}
+
+#[async_trait]
+impl DocumentLoader<SourceMetadata> for WebPageLoader {
+    async fn load(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let response = reqwest::get(&self.url)
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                LoaderError::SourceReadError(format!("Failed to fetch {}: {e}", self.url))
+            })?;
+
+        let is_html = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.contains("html"))
+            .unwrap_or(true);
+
+        let revision = response_revision(response.headers());
+
+        let body = response.text().await.map_err(|e| {
+            LoaderError::SourceReadError(format!("Failed to read body of {}: {e}", self.url))
+        })?;
+
+        let mut metadata = vec![("source_url".to_string(), self.url.clone())];
+
+        let content = if is_html {
+            let extracted = html_to_text(&body);
+            if let Some(title) = extracted.title {
+                metadata.push(("title".to_string(), title));
+            }
+            extracted.text
+        } else {
+            body
+        };
+
+        let mut doc = Document {
+            page_content: content,
+            metadata: Some(metadata),
+        };
+        stamp_document(&mut doc, revision.as_deref());
+
+        Ok(vec![doc])
+    }
+
+    async fn source_revision(&self) -> Result<Option<String>, LoaderError> {
+        let response = reqwest::Client::new()
+            .head(&self.url)
+            .send()
+            .await
+            .map_err(|e| {
+                LoaderError::SourceReadError(format!("Failed to fetch {}: {e}", self.url))
+            })?;
+
+        // Error pages may carry their own ETag, which says nothing of the page
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+
+        Ok(response_revision(response.headers()))
+    }
+}
@@ -1,7 +1,28 @@
-use crate::{DocumentLoader, LoaderError};
+use crate::{
+    content_hash, find_chapter, parse_description_chapters, stamp_document, CaptionCleaner,
+    Chapter, DocumentLoader, LoaderError, SegmentMerger,
+};
 use async_trait::async_trait;
 use llm_chain::schema::Document;
 use serde::Deserialize;
+use url::Url;
+
+/// Caption track URL parameters that change between page fetches
+/// without the captions themselves changing.
+const VOLATILE_CAPTION_PARAMS: &[&str] = &[
+    "expire",
+    "signature",
+    "sparams",
+    "key",
+    "ip",
+    "ipbits",
+    "ei",
+    "opi",
+    "xoaf",
+    "xorp",
+    "caps",
+    "hl",
+];
 
 #[derive(thiserror::Error, Debug)]
 pub enum YoutubeCaptionsLoaderError {
@@ -13,21 +34,56 @@ pub enum YoutubeCaptionsLoaderError {
     ExtractCaptionsJsonError,
 }
 
+/// A caption line and the time span (in seconds) it is shown for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedSegment {
+    pub text: String,
+    pub start: f64,
+    pub duration: f64,
+}
+
+impl TimedSegment {
+    pub fn end(&self) -> f64 {
+        self.start + self.duration
+    }
+}
+
+/// Loads the captions of a YouTube video. The caption text keeps the HTML
+/// entities YouTube escapes it with, decode them with `HtmlEntityDecoder`.
 pub struct YoutubeCaptionsLoader {
     video_id: String,
+    caption_cleaner: Option<CaptionCleaner>,
+    segment_merger: Option<SegmentMerger>,
 }
 
 impl YoutubeCaptionsLoader {
     pub fn new(video_id: String) -> Self {
-        Self { video_id }
+        Self {
+            video_id,
+            caption_cleaner: None,
+            segment_merger: None,
+        }
+    }
+
+    /// Cleans auto-generated caption tracks with `cleaner`. Manually
+    /// written tracks are left as they are.
+    pub fn with_caption_cleaner(mut self, cleaner: CaptionCleaner) -> Self {
+        self.caption_cleaner = Some(cleaner);
+        self
+    }
+
+    /// Loads one document per paragraph merged by `merger`, each with its
+    /// `start_seconds` and `end_seconds` (and the metadata of its chapter,
+    /// when the description has chapters), instead of a single document
+    /// for the whole transcript.
+    pub fn with_paragraphs(mut self, merger: SegmentMerger) -> Self {
+        self.segment_merger = Some(merger);
+        self
     }
 
     async fn fetch_html(&self) -> Result<String, reqwest::Error> {
         let url = self.create_url(&self.video_id);
-        let body = reqwest::get(url).await?.text().await?;
-        let text = html_escape::decode_html_entities(&body);
-
-        Ok(text.into_owned())
+        reqwest::get(url).await?.text().await
     }
 
     async fn extract_captions_json(
@@ -56,6 +112,27 @@ impl YoutubeCaptionsLoader {
         Ok(captions_list)
     }
 
+    /// Chapters from the description in the `videoDetails` of the page.
+    fn extract_chapters(&self, html: &str) -> Vec<Chapter> {
+        let Some((_, details)) = html.split_once(r#""videoDetails":"#) else {
+            return vec![];
+        };
+        let Some(Ok(details)) = serde_json::Deserializer::from_str(details)
+            .into_iter::<serde_json::Value>()
+            .next()
+        else {
+            return vec![];
+        };
+
+        let duration = details["lengthSeconds"]
+            .as_str()
+            .and_then(|l| l.parse::<f64>().ok());
+        details["shortDescription"]
+            .as_str()
+            .map(|description| parse_description_chapters(description, duration))
+            .unwrap_or_default()
+    }
+
     fn create_url(&self, video_id: &str) -> String {
         format!("https://www.youtube.com/watch?v={video_id}")
     }
@@ -81,6 +158,30 @@ struct CaptionTrack {
     language_code: String,
     is_translatable: bool,
     kind: String,
+    #[serde(default)]
+    last_modified: Option<String>,
+}
+
+impl CaptionTrack {
+    /// Revision of the track: `lastModified` when YouTube provides it,
+    /// otherwise a hash of the stable `base_url` parameters.
+    fn revision(&self) -> String {
+        if let Some(last_modified) = &self.last_modified {
+            return format!("lastModified:{last_modified}");
+        }
+
+        let mut params = Url::parse(&self.base_url)
+            .map(|url| {
+                url.query_pairs()
+                    .filter(|(k, _)| !VOLATILE_CAPTION_PARAMS.contains(&k.as_ref()))
+                    .map(|(k, v)| format!("{k}={v}"))
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_else(|_| vec![self.base_url.clone()]);
+        params.sort();
+
+        format!("captions:{}", content_hash(&params.join("&")))
+    }
 }
 
 #[derive(Debug)]
@@ -91,6 +192,7 @@ struct Transcript {
     is_generated: bool,
     is_translatable: bool,
     translation_langs: Option<Vec<TranslationLanguage>>,
+    revision: String,
 }
 
 impl Transcript {
@@ -108,6 +210,7 @@ impl Transcript {
                     is_translatable: t.is_translatable,
                     is_generated: t.kind == "asr",
                     url: t.base_url.clone(),
+                    revision: t.revision(),
                 })
                 .collect();
 
@@ -117,17 +220,27 @@ impl Transcript {
         }
     }
 
-    pub async fn fetch(&self) -> Result<Vec<String>, reqwest::Error> {
+    pub async fn fetch(&self) -> Result<Vec<TimedSegment>, reqwest::Error> {
         let res = reqwest::get(self.url.clone()).await?.text().await.unwrap();
         let doc = roxmltree::Document::parse(&res).unwrap();
 
-        let mut transcript: Vec<String> = Vec::new();
+        let mut transcript: Vec<TimedSegment> = Vec::new();
 
         let nodes = doc.descendants().filter(|x| x.tag_name() == "text".into());
 
         for node in nodes {
-            let text = html_escape::decode_html_entities(node.text().unwrap());
-            transcript.push(text.into());
+            let text = node.text().unwrap_or_default();
+            let seconds = |attribute: &str| {
+                node.attribute(attribute)
+                    .and_then(|v| v.parse::<f64>().ok())
+                    .unwrap_or_default()
+            };
+
+            transcript.push(TimedSegment {
+                text: text.into(),
+                start: seconds("start"),
+                duration: seconds("dur"),
+            });
         }
 
         Ok(transcript)
@@ -153,12 +266,13 @@ impl DocumentLoader<YoutubeCaptionsLoaderMetadata> for YoutubeCaptionsLoader {
         // @TODO: Check if recaptcha and error out
         // @TODO: Check for playbality status
 
+        let chapters = self.extract_chapters(&html_str);
         let caps = self.extract_captions_json(html_str).await.unwrap();
         let transcripts = Transcript::from_captions_list(caps, self.video_id.clone())?;
 
         let transcript = transcripts.get(0).unwrap();
 
-        let transcript_strs = transcript.fetch().await.unwrap();
+        let segments = transcript.fetch().await.unwrap();
         let translation_langs = match &transcript.translation_langs {
             Some(langs) => langs
                 .iter()
@@ -168,7 +282,7 @@ impl DocumentLoader<YoutubeCaptionsLoaderMetadata> for YoutubeCaptionsLoader {
             None => String::from(""),
         };
 
-        let metadata = vec![
+        let mut metadata = vec![
             ("video_id".to_string(), transcript.video_id.clone()),
             (
                 "language_code".to_string(),
@@ -185,10 +299,59 @@ impl DocumentLoader<YoutubeCaptionsLoaderMetadata> for YoutubeCaptionsLoader {
             ),
         ];
 
-        Ok(vec![Document {
-            page_content: transcript_strs.join(" "),
-            metadata: Some(metadata),
-        }])
+        let cleaned = match &self.caption_cleaner {
+            Some(cleaner) if transcript.is_generated => {
+                let cleaned = cleaner.clean(&segments);
+                metadata.extend(cleaned.metadata());
+                Some(cleaned)
+            }
+            _ => None,
+        };
+
+        let Some(merger) = &self.segment_merger else {
+            let page_content = match &cleaned {
+                Some(cleaned) => cleaned.text(),
+                None => segments
+                    .iter()
+                    .map(|s| s.text.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(" "),
+            };
+
+            let mut doc = Document {
+                page_content,
+                metadata: Some(metadata),
+            };
+            stamp_document(&mut doc, Some(&transcript.revision));
+
+            return Ok(vec![doc]);
+        };
+
+        let paragraphs = match &cleaned {
+            Some(cleaned) => merger.merge_cleaned(cleaned),
+            None => merger.merge(&segments),
+        };
+        let docs = paragraphs
+            .into_iter()
+            .enumerate()
+            .map(|(index, paragraph)| {
+                let mut paragraph_metadata = metadata.clone();
+                paragraph_metadata.push(("paragraph_index".to_string(), index.to_string()));
+                paragraph_metadata.extend(paragraph.metadata());
+                if let Some(chapter_index) = find_chapter(&chapters, paragraph.start) {
+                    paragraph_metadata.extend(chapters[chapter_index].metadata(chapter_index));
+                }
+
+                let mut doc = Document {
+                    page_content: paragraph.text,
+                    metadata: Some(paragraph_metadata),
+                };
+                stamp_document(&mut doc, Some(&transcript.revision));
+                doc
+            })
+            .collect();
+
+        Ok(docs)
     }
 }
 
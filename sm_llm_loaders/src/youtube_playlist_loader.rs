@@ -0,0 +1,71 @@
+use async_trait::async_trait;
+
+use crate::{Document, DocumentLoader, LoaderError, SourceMetadata, YoutubeCaptionsLoader};
+
+/// Loads the captions of every video in a YouTube playlist, one
+/// `YoutubeCaptionsLoader` per video. Each document gets an extra
+/// `playlist_id` metadata entry.
+pub struct YoutubePlaylistLoader {
+    playlist_id: String,
+}
+
+impl YoutubePlaylistLoader {
+    pub fn new(playlist_id: String) -> Self {
+        Self { playlist_id }
+    }
+
+    /// Fetches the playlist page and scrapes the video ids off it, in
+    /// playlist order and without duplicates.
+    pub async fn fetch_video_ids(&self) -> Result<Vec<String>, LoaderError> {
+        let url = format!("https://www.youtube.com/playlist?list={}", self.playlist_id);
+
+        let html = reqwest::get(url)
+            .await
+            .map_err(|e| LoaderError::SourceReadError(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| LoaderError::SourceReadError(e.to_string()))?;
+
+        Ok(extract_video_ids(&html))
+    }
+}
+
+fn extract_video_ids(html: &str) -> Vec<String> {
+    let video_id_key = r#""videoId":""#;
+
+    let mut ids: Vec<String> = vec![];
+    for part in html.split(video_id_key).skip(1) {
+        let id = part.split('"').next().unwrap_or_default();
+        if !id.is_empty() && !ids.iter().any(|i| i == id) {
+            ids.push(id.to_string());
+        }
+    }
+
+    ids
+}
+
+#[async_trait]
+impl DocumentLoader<SourceMetadata> for YoutubePlaylistLoader {
+    async fn load(&self) -> Result<Vec<Document<SourceMetadata>>, LoaderError> {
+        let video_ids = self.fetch_video_ids().await?;
+        if video_ids.is_empty() {
+            return Err(LoaderError::SourceReadError(format!(
+                "No videos found in playlist {}",
+                self.playlist_id
+            )));
+        }
+
+        let mut docs = vec![];
+        for video_id in video_ids {
+            let loader = YoutubeCaptionsLoader::new(video_id);
+            for mut doc in loader.load().await? {
+                doc.metadata
+                    .get_or_insert_with(Vec::new)
+                    .push(("playlist_id".to_string(), self.playlist_id.clone()));
+                docs.push(doc);
+            }
+        }
+
+        Ok(docs)
+    }
+}
@@ -0,0 +1,42 @@
+use std::io::{Cursor, Read};
+
+use zip::ZipArchive;
+
+use crate::LoaderError;
+
+/// In-memory view over a ZIP based document format (EPUB, DOCX, ...).
+pub(crate) struct ZipContainer {
+    archive: ZipArchive<Cursor<Vec<u8>>>,
+}
+
+impl ZipContainer {
+    pub fn new(bytes: Vec<u8>) -> Result<Self, LoaderError> {
+        let archive = ZipArchive::new(Cursor::new(bytes))
+            .map_err(|e| LoaderError::SourceReadError(format!("Invalid ZIP container: {e}")))?;
+
+        Ok(Self { archive })
+    }
+
+    pub fn has_entry(&self, name: &str) -> bool {
+        self.archive.file_names().any(|n| n == name)
+    }
+
+    /// Reads a UTF-8 entry of the container.
+    pub fn read_string(&mut self, name: &str) -> Result<String, LoaderError> {
+        let mut entry = self
+            .archive
+            .by_name(name)
+            .map_err(|e| LoaderError::SourceReadError(format!("Missing entry {name}: {e}")))?;
+
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+
+        Ok(content)
+    }
+}
+
+/// Parses an XML entry, mapping parse errors to `LoaderError`.
+pub(crate) fn parse_xml(content: &str) -> Result<roxmltree::Document<'_>, LoaderError> {
+    roxmltree::Document::parse(content)
+        .map_err(|e| LoaderError::SourceReadError(format!("Invalid XML: {e}")))
+}
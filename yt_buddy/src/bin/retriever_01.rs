@@ -1,10 +1,37 @@
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use llm_chain::traits::VectorStore;
 use llm_chain_qdrant::Qdrant;
 use qdrant_client::prelude::{QdrantClient, QdrantClientConfig};
 use yt_buddy::{Ingester, YTIngestMetadata, YoutubeCaptionsIngester};
-use yt_buddy_core::RSBertEmbeddings;
+use yt_buddy_core::{
+    ChunkingPolicy, RSBertEmbeddings, SentenceEmbeddingsModelType, MODEL_DIR_ENV_VAR,
+};
+
+/// The model, relative to the current directory or to the executable.
+const RELATIVE_MODEL_DIR: &str = "resources/all-MiniLM-L12-v2";
+
+/// The model directory given as the first argument, else in
+/// `YT_BUDDY_MODEL_DIR`, else the first `RELATIVE_MODEL_DIR` that exists.
+fn find_model_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::args_os().nth(1) {
+        return Some(dir.into());
+    }
+    if let Some(dir) = std::env::var_os(MODEL_DIR_ENV_VAR).filter(|dir| !dir.is_empty()) {
+        return Some(dir.into());
+    }
+
+    let current_dir = std::env::current_dir().ok();
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    [current_dir, exe_dir]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(RELATIVE_MODEL_DIR))
+        .find(|dir| dir.is_dir())
+}
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
@@ -21,7 +48,16 @@ async fn main() {
     let collection_name = "retriever_01".to_string();
 
     dbg!("Creating Rs Bert Embeddings..");
-    let embeddings = RSBertEmbeddings::new().expect("Failed to create RSBertEmbeddings");
+    let model_dir = find_model_dir().unwrap_or_else(|| {
+        panic!(
+            "No model found: pass its directory as the first argument, set {MODEL_DIR_ENV_VAR} \
+             or put it in {RELATIVE_MODEL_DIR} under the current directory or the executable's"
+        )
+    });
+    let embeddings = RSBertEmbeddings::builder(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+        .with_model_path(model_dir)
+        .build()
+        .expect("Failed to create RSBertEmbeddings");
 
     dbg!("Creating ingester..");
     let video_id = "yriZBFKE9JU";
@@ -31,6 +67,15 @@ async fn main() {
     dbg!(collections_list);
 
     let embeddings_size = embeddings.get_embeddings_size();
+    let tokenizer = embeddings
+        .create_tokenizer()
+        .expect("Failed to load the model tokenizer");
+    let chunking_policy = ChunkingPolicy::for_max_sequence_length(
+        embeddings
+            .max_sequence_length()
+            .expect("Unknown max sequence length"),
+    )
+    .expect("Invalid chunking policy");
 
     dbg!("Creating VectorStore..");
     let qdrant_vs: Arc<Qdrant<RSBertEmbeddings, YTIngestMetadata>> = Arc::new(Qdrant::new(
@@ -49,6 +94,8 @@ async fn main() {
     //         qdrant_vs.clone(),
     //         collection_name.to_string(),
     //         embeddings_size,
+    //         tokenizer,
+    //         chunking_policy,
     //     )
     // });
 
@@ -64,6 +111,8 @@ async fn main() {
         qdrant_vs.clone(),
         collection_name.to_string(),
         embeddings_size,
+        tokenizer,
+        chunking_policy,
     )
     .await
     .expect("Failed to create ingester");
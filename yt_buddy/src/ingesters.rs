@@ -3,15 +3,18 @@ use std::sync::Arc;
 use async_trait::async_trait;
 
 use llm_chain::tokens::TokenizerError;
-use llm_chain::traits::VectorStore;
-use llm_chain::TextSplitter;
+use llm_chain::traits::{Embeddings, VectorStore};
 use llm_chain_qdrant::Qdrant;
 
 use qdrant_client::qdrant::{CreateCollection, Distance, VectorParams, VectorsConfig};
-use yt_buddy_core::{RSBertEmbeddings, RSBertError, RsBertTextSplitter};
+use yt_buddy_core::{
+    BatchSplitter, ChunkingPolicy, ChunkingPolicyError, DocumentSplitError, ModelTokenizer,
+    RSBertEmbeddings, RSBertError, RsBertTokenizer, SplitError,
+};
 
 use sm_llm_loaders::{
-    Document, DocumentLoader, LoaderError, YoutubeCaptionsLoader, YoutubeCaptionsLoaderMetadata,
+    Document, DocumentLoader, HtmlEntityDecoder, LoaderError, TransformingLoader,
+    YoutubeCaptionsLoader, YoutubeCaptionsLoaderMetadata,
 };
 
 use qdrant_client::prelude::QdrantClient;
@@ -28,34 +31,65 @@ pub enum YoutubeCaptionsIngesterError {
     TokenizerError(#[from] TokenizerError),
     #[error(transparent)]
     EmbeddingError(#[from] RSBertError),
+    #[error(transparent)]
+    ChunkingError(#[from] ChunkingPolicyError),
+    #[error("{} documents failed to split, the others were ingested", .0.len())]
+    SplitErrors(Vec<DocumentSplitError>),
     #[error("VectorStore/Client error: {0}")]
     VectorStoreError(String),
     #[error("Model Error: {0}")]
     ModelError(String),
 }
 
-pub struct YoutubeCaptionsIngester {
+/// Ingests the captions of a video, with `RSBertEmbeddings` by default.
+/// Any embeddings can be used along with the tokenizer of their model,
+/// e.g. `HashingEmbeddings` and its `WordTokenizer` to run without model
+/// files.
+pub struct YoutubeCaptionsIngester<E = RSBertEmbeddings, S = RsBertTokenizer>
+where
+    E: Embeddings + Send + Sync,
+    S: ModelTokenizer + Clone + 'static,
+{
     video_id: String,
     collection_name: String,
     qdrant_client: Arc<QdrantClient>,
-    vector_store: Arc<Qdrant<RSBertEmbeddings, YTIngestMetadata>>,
+    vector_store: Arc<Qdrant<E, YTIngestMetadata>>,
     embeddings_size: u64,
+    splitter: Arc<BatchSplitter<S>>,
+    chunking_policy: ChunkingPolicy,
 }
 
-impl YoutubeCaptionsIngester {
+impl<E, S> YoutubeCaptionsIngester<E, S>
+where
+    E: Embeddings + Send + Sync,
+    S: ModelTokenizer + Clone + 'static,
+{
+    /// `tokenizer` must be the tokenizer of the embedding model, the
+    /// `chunking_policy` is checked against its max sequence length.
     pub async fn new(
         video_id: String,
         qdrant_client: Arc<QdrantClient>,
-        vector_store: Arc<Qdrant<RSBertEmbeddings, YTIngestMetadata>>,
+        vector_store: Arc<Qdrant<E, YTIngestMetadata>>,
         collection_name: String,
         embeddings_size: u64,
+        tokenizer: S,
+        chunking_policy: ChunkingPolicy,
     ) -> Result<Self, YoutubeCaptionsIngesterError> {
+        let max_sequence_length = tokenizer.max_seq_length().ok_or_else(|| {
+            YoutubeCaptionsIngesterError::ModelError(
+                "Unknown max sequence length of the embedding model".to_string(),
+            )
+        })?;
+        chunking_policy.validate_for(max_sequence_length)?;
+
         Ok(Self {
             video_id,
             qdrant_client,
             collection_name,
             vector_store,
             embeddings_size,
+            splitter: Arc::new(BatchSplitter::new(tokenizer)),
+            chunking_policy,
         })
     }
 
@@ -99,44 +133,41 @@ impl YoutubeCaptionsIngester {
 }
 
 #[async_trait]
-impl Ingester<YTIngestMetadata> for YoutubeCaptionsIngester {
-    type Embeddings = RSBertEmbeddings;
+impl<E, S> Ingester<YTIngestMetadata> for YoutubeCaptionsIngester<E, S>
+where
+    E: Embeddings + Send + Sync,
+    S: ModelTokenizer + Clone + 'static,
+{
+    type Embeddings = E;
     type VecStore = Qdrant<Self::Embeddings, YTIngestMetadata>;
     type Error = YoutubeCaptionsIngesterError;
 
     async fn ingest(&self) -> Result<(), Self::Error> {
         // Get captions text from video id
-        let loader = YoutubeCaptionsLoader::new(self.video_id.clone());
+        let loader = TransformingLoader::new(
+            YoutubeCaptionsLoader::new(self.video_id.clone()),
+            HtmlEntityDecoder,
+        );
         let docs = loader.load().await?;
 
-        let first_doc =
-            docs.get(0)
-                .ok_or(Self::Error::LoaderError(LoaderError::SourceReadError(
-                    "No documents retrieved".to_string(),
-                )))?;
-        let metadata = first_doc.metadata.clone();
-
-        // Split text into documents
-        // Creating the embeddings first will give access to the model
-        let embeddings = RSBertEmbeddings::new().expect("Failed to create RsBertEmbeddings");
-
-        let splitter = RsBertTextSplitter::new(embeddings.get_model());
-        // let split_texts = splitter.split_text(&docs.get(0).unwrap().page_content, 384, 16)?;
+        if docs.is_empty() {
+            return Err(Self::Error::LoaderError(LoaderError::SourceReadError(
+                "No documents retrieved".to_string(),
+            )));
+        }
 
-        let embeddings_size: usize = self.embeddings_size.try_into().map_err(|e| {
-            YoutubeCaptionsIngesterError::ModelError(format!(
-                "Failed to convert embeddings size to usize: {e:?}"
-            ))
-        })?;
+        // Splitting is CPU-bound, keep it off the async workers
+        let splitter = self.splitter.clone();
+        let chunking_policy = self.chunking_policy;
+        let (split_docs, split_errors) = tokio::task::spawn_blocking(move || {
+            split_for_ingest(&splitter, &chunking_policy, &docs)
+        })
+        .await
+        .map_err(|e| YoutubeCaptionsIngesterError::ModelError(e.to_string()))?;
 
-        let split_docs = splitter
-            .split_text(&docs.get(0).unwrap().page_content, embeddings_size, 16)?
-            .into_iter()
-            .map(|t| Document {
-                page_content: t,
-                metadata: metadata.clone(),
-            })
-            .collect::<Vec<Document<_>>>();
+        if split_docs.is_empty() && !split_errors.is_empty() {
+            return Err(YoutubeCaptionsIngesterError::SplitErrors(split_errors));
+        }
 
         dbg!(&split_docs);
 
@@ -154,6 +185,227 @@ impl Ingester<YTIngestMetadata> for YoutubeCaptionsIngester {
 
         dbg!("Vectors store under IDs: {:?}", doc_ids);
 
+        if !split_errors.is_empty() {
+            return Err(YoutubeCaptionsIngesterError::SplitErrors(split_errors));
+        }
+
         Ok(())
     }
 }
+
+/// Splits the documents in parallel into chunks of the policy, along with
+/// the errors of the documents that failed to split.
+fn split_for_ingest<S: ModelTokenizer>(
+    splitter: &BatchSplitter<S>,
+    chunking_policy: &ChunkingPolicy,
+    docs: &[Document<YTIngestMetadata>],
+) -> (Vec<Document<YTIngestMetadata>>, Vec<DocumentSplitError>) {
+    let mut split_docs = vec![];
+    let mut split_errors = vec![];
+    for (index, split) in splitter
+        .split_documents(
+            docs,
+            chunking_policy.target_tokens,
+            chunking_policy.overlap_tokens,
+        )
+        .into_iter()
+        .enumerate()
+    {
+        let fitted = split.and_then(|chunks| {
+            fit_chunks(splitter.get_splitter(), chunking_policy, chunks)
+                .map_err(|error| DocumentSplitError { index, error })
+        });
+        match fitted {
+            Ok(chunks) => split_docs.extend(chunks),
+            Err(e) => split_errors.push(e),
+        }
+    }
+
+    (split_docs, split_errors)
+}
+
+/// Chunk boundaries can tokenize differently on their own, the chunks of
+/// a document that come out over the hard max are re-split rather than
+/// truncated when embedded.
+fn fit_chunks<S: ModelTokenizer>(
+    tokenizer: &S,
+    chunking_policy: &ChunkingPolicy,
+    chunks: Vec<Document<YTIngestMetadata>>,
+) -> Result<Vec<Document<YTIngestMetadata>>, SplitError> {
+    let texts: Vec<&str> = chunks.iter().map(|doc| doc.page_content.as_str()).collect();
+    let tokens = tokenizer.count_tokens_list(&texts);
+
+    let mut fitted = Vec::with_capacity(chunks.len());
+    for (chunk, tokens) in chunks.into_iter().zip(tokens) {
+        if chunking_policy.check_chunk(tokens).is_ok() {
+            fitted.push(chunk);
+            continue;
+        }
+        for page_content in chunking_policy.fit_chunk(tokenizer, chunk.page_content)? {
+            chunking_policy.check_chunk(tokenizer.count_tokens(&page_content))?;
+            fitted.push(Document {
+                page_content,
+                metadata: chunk.metadata.clone(),
+            });
+        }
+    }
+
+    Ok(fitted)
+}
+
+#[cfg(test)]
+mod tests {
+    use llm_chain::tokens::Tokenizer;
+    use llm_chain::TextSplitter;
+    use qdrant_client::prelude::QdrantClientConfig;
+    use yt_buddy_core::{ChunkingPolicyError, HashingEmbeddings, WordTokenizer};
+
+    use super::*;
+
+    /// Counts "huge" as 100 tokens but splits it as one word, like a word
+    /// the model tokenizes very differently on its own.
+    #[derive(Clone)]
+    struct HugeWordTokenizer(WordTokenizer);
+
+    impl Tokenizer<String> for HugeWordTokenizer {
+        fn tokenize_str(&self, doc: &str) -> Result<Vec<String>, TokenizerError> {
+            self.0.tokenize_str(doc)
+        }
+
+        fn to_string(&self, tokens: Vec<String>) -> Result<String, TokenizerError> {
+            self.0.to_string(tokens)
+        }
+    }
+
+    impl TextSplitter<String> for HugeWordTokenizer {
+        fn split_text(
+            &self,
+            doc: &str,
+            max_tokens_per_chunk: usize,
+            chunk_overlap: usize,
+        ) -> Result<Vec<String>, TokenizerError> {
+            self.0.split_text(doc, max_tokens_per_chunk, chunk_overlap)
+        }
+    }
+
+    impl ModelTokenizer for HugeWordTokenizer {
+        type TokenType = String;
+
+        fn max_seq_length(&self) -> Option<usize> {
+            self.0.max_seq_length()
+        }
+
+        fn count_tokens(&self, text: &str) -> usize {
+            self.0.count_tokens(text)
+                + 99 * text.split_whitespace().filter(|w| *w == "huge").count()
+        }
+    }
+
+    #[tokio::test]
+    async fn new_without_model_test() {
+        // The client only connects when used
+        let client = Arc::new(
+            QdrantClient::new(Some(QdrantClientConfig::from_url("http://localhost:6334")))
+                .await
+                .unwrap(),
+        );
+        let embeddings = HashingEmbeddings::new().with_max_sequence_length(64);
+        let tokenizer = embeddings.create_tokenizer();
+        let embeddings_size = embeddings.get_embeddings_size();
+        let vector_store = Arc::new(Qdrant::new(
+            client.clone(),
+            "test".to_string(),
+            embeddings,
+            None,
+            None,
+        ));
+
+        let ingester = |chunking_policy| {
+            YoutubeCaptionsIngester::new(
+                "yriZBFKE9JU".to_string(),
+                client.clone(),
+                vector_store.clone(),
+                "test".to_string(),
+                embeddings_size,
+                tokenizer.clone(),
+                chunking_policy,
+            )
+        };
+
+        assert!(
+            ingester(ChunkingPolicy::for_max_sequence_length(64).unwrap())
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            ingester(ChunkingPolicy::new(64, 8, 64).unwrap()).await,
+            Err(YoutubeCaptionsIngesterError::ChunkingError(
+                ChunkingPolicyError::ExceedsModel { .. }
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn split_and_embed_without_model_test() {
+        let embeddings = HashingEmbeddings::new().with_max_sequence_length(18);
+        let tokenizer = embeddings.create_tokenizer();
+        let chunking_policy = ChunkingPolicy::for_max_sequence_length(18).unwrap();
+        let splitter = BatchSplitter::new(tokenizer.clone());
+
+        let words: Vec<String> = (0..40).map(|i| format!("word{i}")).collect();
+        let metadata = Some(vec![("video_id".to_string(), "yriZBFKE9JU".to_string())]);
+        let docs = vec![
+            Document {
+                page_content: words.join(" "),
+                metadata: metadata.clone(),
+            },
+            Document {
+                page_content: "A short caption.".to_string(),
+                metadata: metadata.clone(),
+            },
+        ];
+
+        let (chunks, errors) = split_for_ingest(&splitter, &chunking_policy, &docs);
+        assert!(errors.is_empty());
+        assert_eq!(chunks.len(), 4);
+        for chunk in chunks.iter() {
+            assert!(chunking_policy
+                .check_chunk(tokenizer.count_tokens(&chunk.page_content))
+                .is_ok());
+            assert_eq!(chunk.metadata, metadata);
+        }
+
+        let texts = chunks.iter().map(|c| c.page_content.clone()).collect();
+        let vectors = embeddings.embed_texts(texts).await.unwrap();
+        assert_eq!(vectors.len(), chunks.len());
+        assert!(vectors
+            .iter()
+            .all(|v| v.len() as u64 == embeddings.get_embeddings_size()));
+    }
+
+    #[test]
+    fn split_for_ingest_errors_test() {
+        let tokenizer = HugeWordTokenizer(WordTokenizer::new().with_max_seq_length(18));
+        let chunking_policy = ChunkingPolicy::for_max_sequence_length(18).unwrap();
+        let splitter = BatchSplitter::new(tokenizer);
+
+        let docs: Vec<Document<YTIngestMetadata>> =
+            ["first caption", "a huge caption", "last caption"]
+                .iter()
+                .map(|text| Document {
+                    page_content: text.to_string(),
+                    metadata: None,
+                })
+                .collect();
+
+        let (chunks, errors) = split_for_ingest(&splitter, &chunking_policy, &docs);
+        let texts: Vec<&str> = chunks.iter().map(|c| c.page_content.as_str()).collect();
+        assert_eq!(texts, vec!["first caption", "last caption"]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].index, 1);
+        assert!(matches!(
+            errors[0].error,
+            SplitError::ChunkingError(ChunkingPolicyError::ChunkTooLong { .. })
+        ));
+    }
+}
@@ -0,0 +1,505 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use llm_chain::traits::{Embeddings, EmbeddingsError};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+const EMBEDDINGS_TREE: &str = "embeddings";
+/// Last use of the embeddings, for evicting the least recently used
+const ORDER_TREE: &str = "order";
+
+#[derive(Debug, Error)]
+pub enum CachedEmbeddingsError<E> {
+    #[error("Embeddings error: {0}")]
+    EmbeddingsError(E),
+    #[error("Cache error: {0}")]
+    CacheError(#[from] sled::Error),
+    #[error("Got {actual} embeddings for {expected} texts")]
+    EmbeddingsCountMismatch { expected: usize, actual: usize },
+}
+
+impl<E: Debug> EmbeddingsError for CachedEmbeddingsError<E> {}
+
+/// Identifies the model embeddings were computed with, so that cached
+/// embeddings of another model, or of other weights of it, are never used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelFingerprint {
+    pub name: String,
+    /// Hex SHA-256 of the model weights
+    pub weights_hash: String,
+}
+
+impl ModelFingerprint {
+    pub fn new(name: impl Into<String>, weights_hash: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            weights_hash: weights_hash.into(),
+        }
+    }
+
+    /// Fingerprint of the model in `model_dir`, hashing its weights (the
+    /// `.ot` files of the directory and its module directories).
+    pub fn from_model_dir<S: Into<String>>(name: S, model_dir: &Path) -> std::io::Result<Self> {
+        let mut weights = vec![];
+        weight_files(model_dir, &mut weights)?;
+        weights.sort();
+
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 1 << 16];
+        for path in weights {
+            let relative = path.strip_prefix(model_dir).unwrap_or(&path);
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update([0]);
+
+            let mut file = std::fs::File::open(&path)?;
+            loop {
+                match file.read(&mut buffer)? {
+                    0 => break,
+                    n => hasher.update(&buffer[..n]),
+                }
+            }
+        }
+
+        Ok(Self {
+            name: name.into(),
+            weights_hash: to_hex(&hasher.finalize()),
+        })
+    }
+
+    /// Cache key of `text` for this model.
+    pub fn key(&self, text: &str) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for part in [&self.name, &self.weights_hash] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.update(normalize_text(text).as_bytes());
+
+        hasher.finalize().into()
+    }
+}
+
+/// Text as it's keyed in the cache: trimmed, with any whitespace run
+/// replaced by a single space.
+pub fn normalize_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Statistics of a `CachedEmbeddings`, hits and misses since it was
+/// opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// Texts that didn't need embedding, cached or repeated in a batch
+    pub hits: u64,
+    /// Texts embedded by the inner embeddings
+    pub misses: u64,
+    pub entries: u64,
+    /// Bytes of the keys and values of the entries
+    pub size_bytes: u64,
+}
+
+/// Caches the embeddings of any `Embeddings` in a sled database, so that
+/// re-ingesting unchanged chunks doesn't recompute their embeddings.
+///
+/// Embeddings are keyed by the SHA-256 of the model fingerprint and of
+/// the normalised text (see `normalize_text`). Only the texts missing from
+/// the cache are sent to the wrapped embeddings, once each. When given a
+/// size limit, the least recently used entries are evicted after inserting
+/// new ones until the entries fit.
+///
+/// The database is read and written in blocking tasks of the tokio
+/// runtime, except when opening it. It can hold the embeddings of several
+/// models, but the size of its entries is only read when opening it: don't
+/// use it through several `CachedEmbeddings` at once.
+pub struct CachedEmbeddings<E> {
+    embeddings: E,
+    fingerprint: ModelFingerprint,
+    store: Arc<Store>,
+    max_bytes: Option<u64>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<E> CachedEmbeddings<E> {
+    /// Opens, or creates, the cache database at `path`.
+    pub fn open<P: AsRef<Path>>(
+        embeddings: E,
+        fingerprint: ModelFingerprint,
+        path: P,
+    ) -> Result<Self, sled::Error> {
+        Self::from_db(embeddings, fingerprint, sled::open(path)?)
+    }
+
+    pub fn from_db(
+        embeddings: E,
+        fingerprint: ModelFingerprint,
+        db: sled::Db,
+    ) -> Result<Self, sled::Error> {
+        let entries = db.open_tree(EMBEDDINGS_TREE)?;
+        let order = db.open_tree(ORDER_TREE)?;
+
+        let mut usage = Usage::default();
+        for entry in entries.iter() {
+            let (key, value) = entry?;
+            usage.entries += 1;
+            usage.size_bytes += (key.len() + value.len()) as u64;
+        }
+
+        Ok(Self {
+            embeddings,
+            fingerprint,
+            store: Arc::new(Store {
+                db,
+                entries,
+                order,
+                usage: Mutex::new(usage),
+            }),
+            max_bytes: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    /// Bytes the entries may take, enforced when inserting.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn get_embeddings(&self) -> &E {
+        &self.embeddings
+    }
+
+    pub fn get_fingerprint(&self) -> &ModelFingerprint {
+        &self.fingerprint
+    }
+
+    pub fn get_stats(&self) -> CacheStats {
+        let usage = *self.store.lock_usage();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: usage.entries,
+            size_bytes: usage.size_bytes,
+        }
+    }
+
+    /// Writes the cache to disk, which sled otherwise does periodically.
+    pub async fn flush(&self) -> Result<(), sled::Error> {
+        self.blocking(|store| store.db.flush().map(|_| ())).await
+    }
+
+    /// Removes every entry, of any model.
+    pub async fn clear(&self) -> Result<(), sled::Error> {
+        self.blocking(|store| store.clear()).await
+    }
+
+    /// Runs `f` on the store in a blocking task.
+    async fn blocking<T, F>(&self, f: F) -> Result<T, sled::Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Store) -> Result<T, sled::Error> + Send + 'static,
+    {
+        let store = self.store.clone();
+        tokio::task::spawn_blocking(move || f(&store))
+            .await
+            .map_err(|e| sled::Error::Io(std::io::Error::other(e)))?
+    }
+}
+
+/// Entries of the cache and the bytes of their keys and values.
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    entries: u64,
+    size_bytes: u64,
+}
+
+/// The trees of a `CachedEmbeddings`, shared with its blocking tasks.
+///
+/// Values are the id of their last use, big-endian so that ids sort like
+/// the `order` keys, followed by the embedding.
+struct Store {
+    db: sled::Db,
+    entries: sled::Tree,
+    order: sled::Tree,
+    /// Locked while writing the trees
+    usage: Mutex<Usage>,
+}
+
+impl Store {
+    fn lock_usage(&self) -> MutexGuard<'_, Usage> {
+        self.usage.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Embeddings of `keys`, marking the cached ones as just used.
+    fn get(&self, keys: &[[u8; 32]]) -> Result<Vec<Option<Vec<f32>>>, sled::Error> {
+        let _usage = self.lock_usage();
+
+        keys.iter()
+            .map(|key| {
+                let Some(value) = self.entries.get(key)? else {
+                    return Ok(None);
+                };
+
+                let id = self.db.generate_id()?.to_be_bytes();
+                let mut used = value.to_vec();
+                used[..8].copy_from_slice(&id);
+                self.entries.insert(key, used)?;
+                self.order.remove(&value[..8])?;
+                self.order.insert(id, key)?;
+
+                Ok(Some(decode(&value)))
+            })
+            .collect()
+    }
+
+    fn insert(
+        &self,
+        embeddings: Vec<([u8; 32], Vec<f32>)>,
+        max_bytes: Option<u64>,
+    ) -> Result<(), sled::Error> {
+        let mut usage = self.lock_usage();
+
+        for (key, embedding) in embeddings {
+            let id = self.db.generate_id()?.to_be_bytes();
+            let mut value = id.to_vec();
+            value.extend(embedding.iter().flat_map(|x| x.to_le_bytes()));
+            if let Some(previous) = self.entries.insert(key, value.as_slice())? {
+                self.order.remove(&previous[..8])?;
+                usage.entries -= 1;
+                usage.size_bytes -= (key.len() + previous.len()) as u64;
+            }
+            self.order.insert(id, &key)?;
+            usage.entries += 1;
+            usage.size_bytes += (key.len() + value.len()) as u64;
+        }
+
+        while usage.size_bytes > max_bytes.unwrap_or(u64::MAX) {
+            let Some((_, least_used)) = self.order.pop_min()? else {
+                break;
+            };
+            if let Some(evicted) = self.entries.remove(&least_used)? {
+                usage.entries -= 1;
+                usage.size_bytes -= (least_used.len() + evicted.len()) as u64;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), sled::Error> {
+        let mut usage = self.lock_usage();
+        self.entries.clear()?;
+        self.order.clear()?;
+        *usage = Usage::default();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<E> Embeddings for CachedEmbeddings<E>
+where
+    E: Embeddings + Send + Sync,
+    E::Error: Sync,
+{
+    type Error = CachedEmbeddingsError<E::Error>;
+
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+        let keys: Vec<[u8; 32]> = texts.iter().map(|t| self.fingerprint.key(t)).collect();
+        let cached = self
+            .blocking({
+                let keys = keys.clone();
+                move |store| store.get(&keys)
+            })
+            .await?;
+
+        let mut embeddings = vec![None; texts.len()];
+        // Key of every text to embed, with the indices of the texts it's for
+        let mut missing: Vec<([u8; 32], Vec<usize>)> = vec![];
+        let mut missing_index: HashMap<[u8; 32], usize> = HashMap::new();
+        for (i, (key, embedding)) in keys.iter().zip(cached).enumerate() {
+            if embedding.is_some() {
+                embeddings[i] = embedding;
+            } else if let Some(m) = missing_index.get(key) {
+                missing[*m].1.push(i);
+            } else {
+                missing_index.insert(*key, missing.len());
+                missing.push((*key, vec![i]));
+            }
+        }
+
+        // A text repeated in the batch is embedded once, only its first
+        // occurrence is a miss
+        let misses = missing.len();
+        self.hits
+            .fetch_add((texts.len() - misses) as u64, Ordering::Relaxed);
+        self.misses.fetch_add(misses as u64, Ordering::Relaxed);
+
+        if !missing.is_empty() {
+            let computed = self
+                .embeddings
+                .embed_texts(
+                    missing
+                        .iter()
+                        .map(|(_, indices)| texts[indices[0]].clone())
+                        .collect(),
+                )
+                .await
+                .map_err(CachedEmbeddingsError::EmbeddingsError)?;
+            if computed.len() != missing.len() {
+                return Err(CachedEmbeddingsError::EmbeddingsCountMismatch {
+                    expected: missing.len(),
+                    actual: computed.len(),
+                });
+            }
+
+            let mut inserted = Vec::with_capacity(missing.len());
+            for ((key, indices), embedding) in missing.into_iter().zip(computed) {
+                for i in indices {
+                    embeddings[i] = Some(embedding.clone());
+                }
+                inserted.push((key, embedding));
+            }
+            let max_bytes = self.max_bytes;
+            self.blocking(move |store| store.insert(inserted, max_bytes))
+                .await?;
+        }
+
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+
+    async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+        let mut embeddings = self.embed_texts(vec![query]).await?;
+        embeddings
+            .pop()
+            .ok_or(CachedEmbeddingsError::EmbeddingsCountMismatch {
+                expected: 1,
+                actual: 0,
+            })
+    }
+}
+
+fn decode(value: &[u8]) -> Vec<f32> {
+    value[8..]
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn weight_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            weight_files(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "ot") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[derive(Debug, Error)]
+    #[error("Length embeddings error")]
+    struct LengthEmbeddingsError;
+
+    impl EmbeddingsError for LengthEmbeddingsError {}
+
+    /// Embeds texts as their length, counting the texts embedded.
+    #[derive(Default)]
+    struct LengthEmbeddings {
+        embedded: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Embeddings for LengthEmbeddings {
+        type Error = LengthEmbeddingsError;
+
+        async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+            self.embedded.fetch_add(texts.len(), Ordering::Relaxed);
+            Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+        }
+
+        async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+            Ok(self.embed_texts(vec![query]).await?.remove(0))
+        }
+    }
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn cached_embeddings_test() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let cache = CachedEmbeddings::from_db(
+            LengthEmbeddings::default(),
+            ModelFingerprint::new("length", "v1"),
+            db.clone(),
+        )
+        .unwrap();
+
+        let first = cache
+            .embed_texts(texts(&["a cat", "a dog", "a  cat "]))
+            .await
+            .unwrap();
+        assert_eq!(first, vec![vec![5.0], vec![5.0], vec![5.0]]);
+        assert_eq!(cache.get_embeddings().embedded.load(Ordering::Relaxed), 2);
+
+        let second = cache
+            .embed_texts(texts(&["a dog", "a bird"]))
+            .await
+            .unwrap();
+        assert_eq!(second, vec![vec![5.0], vec![6.0]]);
+        let stats = cache.get_stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 3, 3));
+
+        // Using "a cat" again, "a dog" is now the least recently used
+        cache.embed_query("a cat".to_string()).await.unwrap();
+        drop(cache);
+
+        // Another model doesn't see these embeddings
+        let max_bytes = 2 * stats.size_bytes / 3;
+        let other = CachedEmbeddings::from_db(
+            LengthEmbeddings::default(),
+            ModelFingerprint::new("length", "v2"),
+            db.clone(),
+        )
+        .unwrap()
+        .with_max_bytes(max_bytes);
+        other.embed_query("a cat".to_string()).await.unwrap();
+        assert_eq!(other.get_stats().misses, 1);
+
+        // Evicting the least recently used entries, "a dog" and "a bird"
+        // of v1
+        let stats = other.get_stats();
+        assert_eq!(stats.entries, 2);
+        assert!(stats.size_bytes <= max_bytes);
+        drop(other);
+
+        let cache = CachedEmbeddings::from_db(
+            LengthEmbeddings::default(),
+            ModelFingerprint::new("length", "v1"),
+            db,
+        )
+        .unwrap();
+        cache.embed_texts(texts(&["a dog", "a cat"])).await.unwrap();
+        assert_eq!((cache.get_stats().hits, cache.get_stats().misses), (1, 1));
+    }
+}
@@ -0,0 +1,456 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use tokio::sync::oneshot;
+
+use crate::RSBertError;
+
+/// Texts encoded per model call, unless configured otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 32;
+
+type EmbeddingsResult = Result<Vec<Vec<f32>>, RSBertError>;
+
+struct EmbeddingJob {
+    texts: Vec<String>,
+    embeddings: Vec<Vec<f32>>,
+    /// Texts handed out to replicas so far
+    next: usize,
+    /// Texts encoded so far
+    done: usize,
+    reply: oneshot::Sender<EmbeddingsResult>,
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: HashMap<u64, EmbeddingJob>,
+    /// Jobs that didn't get a batch yet, served first
+    fresh: VecDeque<u64>,
+    /// Jobs with batches left, served in turns
+    started: VecDeque<u64>,
+    next_id: u64,
+    closed: bool,
+    /// Replica threads still running
+    replicas: usize,
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        // A replica panics outside of the lock only, the queue stays sound
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Locks the model of an encoder, recovering it from a batch that
+/// panicked while holding the lock: the panic failed that batch only, the
+/// model is used as is for the next ones.
+pub(crate) fn lock_model<T>(model: &Mutex<T>) -> MutexGuard<'_, T> {
+    model.lock().unwrap_or_else(|e| {
+        model.clear_poison();
+        e.into_inner()
+    })
+}
+
+/// Unregisters a replica thread when it exits, however it does. The last
+/// one fails the jobs left, no one would encode them.
+struct ReplicaExit<'a>(&'a Shared);
+
+impl Drop for ReplicaExit<'_> {
+    fn drop(&mut self) {
+        let mut queue = self.0.lock();
+        queue.replicas -= 1;
+        if queue.replicas > 0 {
+            return;
+        }
+
+        queue.fresh.clear();
+        queue.started.clear();
+        for (_, job) in queue.jobs.drain() {
+            let _ = job.reply.send(Err(RSBertError::WorkerStopped));
+        }
+    }
+}
+
+struct Handle {
+    shared: Arc<Shared>,
+    batch_size: usize,
+    replicas: usize,
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.available.notify_all();
+    }
+}
+
+/// Handle to threads that own encoders (model replicas) and embed texts
+/// for any number of async callers, without blocking their runtime.
+///
+/// Every call to `embed` queues a job. The replicas take batches of at
+/// most `batch_size` texts from a shared queue: first from jobs that
+/// didn't get a batch yet, then in turns from the others. A short query
+/// waits for the batches being encoded, not for a whole ingest, and the
+/// batches of a large job are spread across all replicas. The threads
+/// stop once all handles are dropped and the queue is empty.
+///
+/// An encoder panicking fails the job of its batch, the replica goes on
+/// with the next one. Should all replicas stop anyway, queued and later
+/// jobs fail with `WorkerStopped`.
+#[derive(Clone)]
+pub struct EmbeddingWorker {
+    handle: Arc<Handle>,
+}
+
+impl EmbeddingWorker {
+    /// Spawns a single worker thread, encoding batches with `encode`.
+    pub fn spawn<F>(encode: F, batch_size: usize) -> Result<Self, RSBertError>
+    where
+        F: FnMut(&[String]) -> EmbeddingsResult + Send + 'static,
+    {
+        Self::spawn_pool(vec![move || encode], batch_size)
+    }
+
+    /// Spawns a thread per replica. Each thread first calls its replica
+    /// to get the encoder it uses, so per-thread settings can be applied
+    /// there.
+    pub fn spawn_pool<R, F>(replicas: Vec<R>, batch_size: usize) -> Result<Self, RSBertError>
+    where
+        R: FnOnce() -> F + Send + 'static,
+        F: FnMut(&[String]) -> EmbeddingsResult,
+    {
+        if replicas.is_empty() {
+            return Err(RSBertError::ModelError("No model replicas".to_string()));
+        }
+
+        let handle = Arc::new(Handle {
+            shared: Arc::default(),
+            batch_size: batch_size.max(1),
+            replicas: replicas.len(),
+        });
+        handle.shared.lock().replicas = replicas.len();
+        for (i, replica) in replicas.into_iter().enumerate() {
+            let shared = handle.shared.clone();
+            let batch_size = handle.batch_size;
+            std::thread::Builder::new()
+                .name(format!("embedding-worker-{i}"))
+                .spawn(move || {
+                    let _exit = ReplicaExit(&shared);
+                    run(&shared, replica(), batch_size)
+                })
+                .map_err(|e| RSBertError::ModelError(format!("Failed to spawn worker: {e}")))?;
+        }
+
+        Ok(Self { handle })
+    }
+
+    pub fn get_batch_size(&self) -> usize {
+        self.handle.batch_size
+    }
+
+    pub fn get_replicas(&self) -> usize {
+        self.handle.replicas
+    }
+
+    /// Embeds `texts`. They are queued when `embed` is called, not when
+    /// the returned future is first polled.
+    pub fn embed(&self, texts: Vec<String>) -> impl Future<Output = EmbeddingsResult> {
+        let (reply, embeddings) = oneshot::channel();
+        if texts.is_empty() {
+            let _ = reply.send(Ok(vec![]));
+        } else {
+            // Checked under the lock of the insert, which the last replica
+            // drains the queue under
+            let mut queue = self.handle.shared.lock();
+            if queue.replicas == 0 {
+                // Dropping `reply` fails the job with `WorkerStopped`
+                drop(reply);
+            } else {
+                let id = queue.next_id;
+                queue.next_id += 1;
+                queue.jobs.insert(
+                    id,
+                    EmbeddingJob {
+                        embeddings: vec![vec![]; texts.len()],
+                        texts,
+                        next: 0,
+                        done: 0,
+                        reply,
+                    },
+                );
+                queue.fresh.push_back(id);
+                drop(queue);
+                self.handle.shared.available.notify_one();
+            }
+        }
+
+        async move { embeddings.await.map_err(|_e| RSBertError::WorkerStopped)? }
+    }
+}
+
+fn run<F>(shared: &Shared, mut encode: F, batch_size: usize)
+where
+    F: FnMut(&[String]) -> EmbeddingsResult,
+{
+    let mut queue = shared.lock();
+
+    loop {
+        let Some(id) = queue
+            .fresh
+            .pop_front()
+            .or_else(|| queue.started.pop_front())
+        else {
+            if queue.closed {
+                return;
+            }
+            queue = shared
+                .available
+                .wait(queue)
+                .unwrap_or_else(|e| e.into_inner());
+            continue;
+        };
+
+        let Some(job) = queue.jobs.get_mut(&id) else {
+            continue;
+        };
+        // The caller is gone, don't bother
+        if job.reply.is_closed() {
+            queue.jobs.remove(&id);
+            continue;
+        }
+
+        let start = job.next;
+        let end = usize::min(start + batch_size, job.texts.len());
+        let texts = job.texts[start..end].to_vec();
+        job.next = end;
+        if end < job.texts.len() {
+            queue.started.push_back(id);
+            shared.available.notify_one();
+        }
+
+        drop(queue);
+        let encoded = catch_unwind(AssertUnwindSafe(|| encode(&texts))).unwrap_or_else(|panic| {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|m| m.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(RSBertError::ModelError(format!(
+                "Encoder panicked: {message}"
+            )))
+        });
+        queue = shared.lock();
+
+        // Gone if another batch of the job failed
+        let Some(job) = queue.jobs.get_mut(&id) else {
+            continue;
+        };
+        let failure = match encoded {
+            Ok(batch) if batch.len() == texts.len() => {
+                for (slot, embedding) in job.embeddings[start..end].iter_mut().zip(batch) {
+                    *slot = embedding;
+                }
+                job.done += texts.len();
+                if job.done < job.texts.len() {
+                    continue;
+                }
+                None
+            }
+            Ok(_) => Some(RSBertError::EmptyEmbeddings),
+            Err(e) => Some(e),
+        };
+
+        let job = queue.jobs.remove(&id).expect("Job was just found");
+        queue.started.retain(|queued| *queued != id);
+        let _ = job.reply.send(match failure {
+            Some(e) => Err(e),
+            None => Ok(job.embeddings),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+    use std::time::Duration;
+
+    use super::*;
+
+    type Encoder = fn(&[String]) -> EmbeddingsResult;
+
+    fn texts(prefix: &str, count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("{prefix}{i}")).collect()
+    }
+
+    #[tokio::test]
+    async fn embed_fairness_test() {
+        let batches: Arc<Mutex<Vec<Vec<String>>>> = Arc::default();
+        let (started, started_receiver) = std::sync::mpsc::channel();
+        let (gate, gate_receiver) = std::sync::mpsc::channel::<()>();
+
+        let recorded = batches.clone();
+        let mut gate_receiver = Some(gate_receiver);
+        let worker = EmbeddingWorker::spawn(
+            move |texts: &[String]| {
+                // Hold the first batch until the second job is queued
+                if let Some(gate) = gate_receiver.take() {
+                    started.send(()).unwrap();
+                    gate.recv().unwrap();
+                }
+                recorded.lock().unwrap().push(texts.to_vec());
+                Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+            },
+            2,
+        )
+        .unwrap();
+
+        let ingest = worker.embed(texts("a", 5));
+        started_receiver.recv().unwrap();
+        let query = worker.embed(texts("b", 1));
+        gate.send(()).unwrap();
+
+        assert_eq!(query.await.unwrap(), vec![vec![2.0]]);
+        assert_eq!(ingest.await.unwrap().len(), 5);
+        assert_eq!(
+            *batches.lock().unwrap(),
+            vec![
+                texts("a", 2),
+                texts("b", 1),
+                vec!["a2".to_string(), "a3".to_string()],
+                vec!["a4".to_string()],
+            ]
+        );
+        assert!(worker.embed(vec![]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn embed_pool_test() {
+        // Both replicas must hold a batch at once to get past the barrier
+        let barrier = Arc::new(Barrier::new(2));
+        let replicas = (0..2)
+            .map(|replica| {
+                let barrier = barrier.clone();
+                move || {
+                    let mut barrier = Some(barrier);
+                    move |texts: &[String]| {
+                        if let Some(barrier) = barrier.take() {
+                            barrier.wait();
+                        }
+                        Ok(texts
+                            .iter()
+                            .map(|t| vec![t[1..].parse::<f32>().unwrap(), replica as f32])
+                            .collect())
+                    }
+                }
+            })
+            .collect();
+        let worker = EmbeddingWorker::spawn_pool(replicas, 1).unwrap();
+        assert_eq!(worker.get_replicas(), 2);
+
+        let embeddings = worker.embed(texts("a", 6)).await.unwrap();
+        let positions: Vec<f32> = embeddings.iter().map(|e| e[0]).collect();
+        assert_eq!(positions, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[tokio::test]
+    async fn embed_panic_test() {
+        let worker = EmbeddingWorker::spawn(
+            |texts: &[String]| {
+                if texts.iter().any(|t| t == "boom") {
+                    panic!("encoder failed");
+                }
+                Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+            },
+            1,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            worker.embed(vec!["boom".to_string()]).await,
+            Err(RSBertError::ModelError(message)) if message.contains("encoder failed")
+        ));
+        assert_eq!(
+            worker.embed(texts("a", 2)).await.unwrap(),
+            vec![vec![2.0]; 2]
+        );
+
+        // No replica left to encode anything
+        let replicas: Vec<fn() -> Encoder> = vec![|| panic!("no model")];
+        let worker = EmbeddingWorker::spawn_pool(replicas, 1).unwrap();
+        assert!(matches!(
+            worker.embed(texts("a", 2)).await,
+            Err(RSBertError::WorkerStopped)
+        ));
+        assert!(matches!(
+            worker.embed(texts("b", 2)).await,
+            Err(RSBertError::WorkerStopped)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn embed_last_replica_exit_test() {
+        for _ in 0..10 {
+            let (stop, stop_receiver) = std::sync::mpsc::channel::<()>();
+            let replica = move || -> Encoder {
+                let _ = stop_receiver.recv();
+                panic!("replica stopped");
+            };
+            let worker = EmbeddingWorker::spawn_pool(vec![replica], 1).unwrap();
+
+            // Jobs queued while the last replica exits
+            let embedder = {
+                let worker = worker.clone();
+                std::thread::spawn(move || {
+                    (0..50)
+                        .map(|i| worker.embed(texts("a", i % 3 + 1)))
+                        .collect::<Vec<_>>()
+                })
+            };
+            stop.send(()).unwrap();
+            let jobs = embedder.join().unwrap();
+
+            let (done, done_receiver) = std::sync::mpsc::channel();
+            tokio::spawn(async move {
+                for job in jobs {
+                    let _ = done.send(job.await);
+                }
+            });
+            for _ in 0..50 {
+                let result = done_receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+                assert!(matches!(result, Err(RSBertError::WorkerStopped)));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn embed_after_model_panic_test() {
+        let model = Arc::new(Mutex::new(0usize));
+        let encoder_model = model.clone();
+        let worker = EmbeddingWorker::spawn(
+            move |texts: &[String]| {
+                let mut calls = lock_model(&encoder_model);
+                *calls += 1;
+                if texts.iter().any(|t| t == "boom") {
+                    panic!("encoder failed");
+                }
+                Ok(texts.iter().map(|_| vec![*calls as f32]).collect())
+            },
+            1,
+        )
+        .unwrap();
+
+        assert!(worker.embed(vec!["boom".to_string()]).await.is_err());
+        assert_eq!(
+            worker.embed(texts("a", 2)).await.unwrap(),
+            vec![vec![2.0], vec![3.0]]
+        );
+        assert!(!model.is_poisoned());
+    }
+}
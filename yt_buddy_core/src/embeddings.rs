@@ -1,17 +1,32 @@
 use std::{
     num::TryFromIntError,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
 use async_trait::async_trait;
 use llm_chain::traits;
 use rust_bert::{
-    pipelines::sentence_embeddings::{SentenceEmbeddingsBuilder, SentenceEmbeddingsModel},
+    pipelines::{
+        common::ModelType,
+        sentence_embeddings::{SentenceEmbeddingsBuilder, SentenceEmbeddingsModel},
+    },
     RustBertError,
 };
+use tch::Device;
 
 use thiserror::Error;
 
+use crate::embedding_worker::{lock_model, EmbeddingWorker, DEFAULT_BATCH_SIZE};
+use crate::text_splitter::{model_type, read_json, read_max_seq_length, vocab_file_names};
+use crate::{ModelFingerprint, RsBertTokenizer};
+
+pub use rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModelType;
+
+/// Environment variable with the model directory, used when no model path
+/// is given to `RSBertEmbeddingsBuilder`.
+pub const MODEL_DIR_ENV_VAR: &str = "YT_BUDDY_MODEL_DIR";
+
 #[derive(Debug, Error)]
 #[error(transparent)]
 pub enum RSBertError {
@@ -23,50 +38,109 @@ pub enum RSBertError {
     ModelError(String),
     #[error("Empty embeddings returned")]
     EmptyEmbeddings,
+    #[error("Embedding worker stopped")]
+    WorkerStopped,
+    #[error("No model directory given, set {MODEL_DIR_ENV_VAR} or a model path or cache dir")]
+    NoModelDir,
+    #[error("Model directory {} is missing: {}", model_dir.display(), missing.join(", "))]
+    MissingModelFiles {
+        model_dir: PathBuf,
+        missing: Vec<String>,
+    },
 }
 
 impl traits::EmbeddingsError for RSBertError {}
 
+/// Sentence embeddings of a rust-bert model.
+///
+/// The model runs on dedicated threads, one per replica of the model (see
+/// `RSBertEmbeddingsBuilder::with_replicas`), sharing a queue of batches,
+/// see `EmbeddingWorker`. Embedding never blocks the async runtime and a
+/// query embedded while an ingest is running waits for the batches being
+/// encoded, not for the whole ingest.
 pub struct RSBertEmbeddings {
     model: Arc<Mutex<SentenceEmbeddingsModel>>,
+    worker: EmbeddingWorker,
+    model_dir: PathBuf,
+    device: Device,
     embeddings_size: u64,
+    max_sequence_length: Option<usize>,
 }
 
 impl RSBertEmbeddings {
+    /// Loads `all-MiniLM-L12-v2` from `$YT_BUDDY_MODEL_DIR`, or else from
+    /// `resources/` (relative to the current directory).
     pub fn new() -> Result<Self, RSBertError> {
-        let model =
-            SentenceEmbeddingsBuilder::local("resources/all-MiniLM-L12-v2").create_model()?;
-
-        let embeddings_size = Self::init_embeddings_size(&model)?;
-
-        debug_assert_eq!(384, embeddings_size);
-
-        Ok(Self {
-            model: Arc::new(Mutex::new(model)),
-            embeddings_size,
-        })
+        RSBertEmbeddingsBuilder::new(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+            .with_cache_dir("resources")
+            .build()
     }
 
+    /// Loads the model in `$YT_BUDDY_MODEL_DIR`, or else in
+    /// `resources/<model_folder>` (relative to the current directory).
     pub fn from_local_model(model_folder: &str) -> Result<Self, RSBertError> {
-        let local_folder_path = format!("resources/{}", model_folder);
-        let model = SentenceEmbeddingsBuilder::local(local_folder_path).create_model()?;
-
-        let embeddings_size = Self::init_embeddings_size(&model)?;
+        RSBertEmbeddingsBuilder::new(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+            .with_cache_dir("resources")
+            .with_model_name(model_folder)
+            .build()
+    }
 
-        Ok(Self {
-            model: Arc::new(Mutex::new(model)),
-            embeddings_size,
-        })
+    pub fn builder(model_type: SentenceEmbeddingsModelType) -> RSBertEmbeddingsBuilder {
+        RSBertEmbeddingsBuilder::new(model_type)
     }
 
+    /// The first replica of the model.
     pub fn get_model(&self) -> Arc<Mutex<SentenceEmbeddingsModel>> {
         self.model.clone()
     }
 
+    /// Max number of texts encoded per model call.
+    pub fn get_batch_size(&self) -> usize {
+        self.worker.get_batch_size()
+    }
+
+    /// Number of replicas of the model embedding in parallel.
+    pub fn get_replicas(&self) -> usize {
+        self.worker.get_replicas()
+    }
+
     pub fn get_embeddings_size(&self) -> u64 {
         self.embeddings_size
     }
 
+    /// Max number of tokens, special tokens included, the model embeds
+    /// before truncating. `None` when the model files don't tell.
+    pub fn max_sequence_length(&self) -> Option<usize> {
+        self.max_sequence_length
+    }
+
+    pub fn get_model_dir(&self) -> &Path {
+        &self.model_dir
+    }
+
+    pub fn get_device(&self) -> Device {
+        self.device
+    }
+
+    /// Fingerprint of the model for `CachedEmbeddings`, named after the
+    /// model directory. Hashes the weights, which takes a while for
+    /// larger models.
+    pub fn fingerprint(&self) -> Result<ModelFingerprint, RSBertError> {
+        let name = self
+            .model_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        ModelFingerprint::from_model_dir(name, &self.model_dir)
+            .map_err(|e| RSBertError::ModelError(format!("Failed to hash model weights: {e}")))
+    }
+
+    /// Loads a standalone tokenizer for the model, see `RsBertTokenizer`.
+    pub fn create_tokenizer(&self) -> Result<RsBertTokenizer, RSBertError> {
+        RsBertTokenizer::from_model_dir(&self.model_dir)
+    }
+
     fn init_embeddings_size(model: &SentenceEmbeddingsModel) -> Result<u64, RSBertError> {
         model
             .get_embedding_dim()?
@@ -75,30 +149,415 @@ impl RSBertEmbeddings {
     }
 }
 
+/// Where to run the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmbeddingsDevice {
+    Cpu,
+    /// The first CUDA device when there is one, else the CPU
+    #[default]
+    CudaIfAvailable,
+}
+
+impl EmbeddingsDevice {
+    pub fn to_device(self) -> Device {
+        match self {
+            EmbeddingsDevice::Cpu => Device::Cpu,
+            EmbeddingsDevice::CudaIfAvailable => Device::cuda_if_available(),
+        }
+    }
+}
+
+/// Builds `RSBertEmbeddings` from a local sentence-transformers model.
+///
+/// The model directory is, in order of precedence:
+/// - the model path given with `with_model_path`,
+/// - the directory in the `YT_BUDDY_MODEL_DIR` environment variable
+///   (see `with_env_var`),
+/// - `<cache dir>/<model name>`, the model name defaulting to the name of
+///   the model type (`all-MiniLM-L12-v2`, ...).
+///
+/// The directory is checked for the files the model needs before loading
+/// it, failing with `RSBertError::MissingModelFiles` listing the missing
+/// ones.
+#[derive(Debug, Clone)]
+pub struct RSBertEmbeddingsBuilder {
+    model_type: SentenceEmbeddingsModelType,
+    model_path: Option<PathBuf>,
+    cache_dir: Option<PathBuf>,
+    model_name: Option<String>,
+    env_var: Option<String>,
+    device: EmbeddingsDevice,
+    batch_size: usize,
+    replicas: usize,
+    threads_per_replica: Option<usize>,
+    memory_limit: Option<u64>,
+}
+
+impl RSBertEmbeddingsBuilder {
+    pub fn new(model_type: SentenceEmbeddingsModelType) -> Self {
+        Self {
+            model_type,
+            model_path: None,
+            cache_dir: None,
+            model_name: None,
+            env_var: Some(MODEL_DIR_ENV_VAR.to_string()),
+            device: EmbeddingsDevice::default(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            replicas: 1,
+            threads_per_replica: None,
+            memory_limit: None,
+        }
+    }
+
+    /// The directory of the model, preferably absolute.
+    pub fn with_model_path<P: Into<PathBuf>>(mut self, model_path: P) -> Self {
+        self.model_path = Some(model_path.into());
+        self
+    }
+
+    /// Directory with model directories, see `with_model_name`.
+    pub fn with_cache_dir<P: Into<PathBuf>>(mut self, cache_dir: P) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Name of the model directory in the cache dir.
+    pub fn with_model_name<S: Into<String>>(mut self, model_name: S) -> Self {
+        self.model_name = Some(model_name.into());
+        self
+    }
+
+    /// Reads the model directory from `env_var` instead of
+    /// `YT_BUDDY_MODEL_DIR`.
+    pub fn with_env_var<S: Into<String>>(mut self, env_var: S) -> Self {
+        self.env_var = Some(env_var.into());
+        self
+    }
+
+    /// Ignores any model directory set in the environment.
+    pub fn without_env_var(mut self) -> Self {
+        self.env_var = None;
+        self
+    }
+
+    pub fn with_device(mut self, device: EmbeddingsDevice) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Max number of texts encoded per model call, 32 by default. Larger
+    /// batches are faster, smaller ones let other callers in sooner.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Loads `replicas` copies of the model, each encoding batches on its
+    /// own thread, 1 by default. See `with_memory_limit`.
+    pub fn with_replicas(mut self, replicas: usize) -> Self {
+        self.replicas = replicas.max(1);
+        self
+    }
+
+    /// Number of threads per replica for torch operations. Torch has a
+    /// single, process-wide, thread pool: `build` sets it to
+    /// `replicas * threads` with `tch::set_num_threads`, which also
+    /// applies to any other model of the process. Left to torch (the
+    /// number of cores) by default.
+    pub fn with_threads_per_replica(mut self, threads: usize) -> Self {
+        self.threads_per_replica = Some(threads.max(1));
+        self
+    }
+
+    /// Bytes the replicas may take, lowering their number to fit. A
+    /// replica is estimated to take the size of the weights of the model
+    /// (its `.ot` files). At least one replica is always loaded.
+    pub fn with_memory_limit(mut self, bytes: u64) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Number of replicas loaded from `model_dir`, within the memory
+    /// limit.
+    pub fn replicas_for(&self, model_dir: &Path) -> usize {
+        let Some(memory_limit) = self.memory_limit else {
+            return self.replicas;
+        };
+
+        match model_weights_size(model_dir) {
+            0 => self.replicas,
+            size => self.replicas.min((memory_limit / size).max(1) as usize),
+        }
+    }
+
+    /// The directory the model is loaded from.
+    pub fn model_dir(&self) -> Result<PathBuf, RSBertError> {
+        if let Some(model_path) = &self.model_path {
+            return Ok(model_path.clone());
+        }
+
+        let from_env = self
+            .env_var
+            .as_ref()
+            .and_then(std::env::var_os)
+            .filter(|dir| !dir.is_empty());
+        if let Some(dir) = from_env {
+            return Ok(PathBuf::from(dir));
+        }
+
+        match &self.cache_dir {
+            Some(cache_dir) => Ok(cache_dir.join(
+                self.model_name
+                    .as_deref()
+                    .unwrap_or(model_type_name(self.model_type)),
+            )),
+            None => Err(RSBertError::NoModelDir),
+        }
+    }
+
+    /// Checks that `model_dir` has the configuration, weights and
+    /// tokenizer files of the model, and the files of the pooling and
+    /// dense modules in its `modules.json`.
+    pub fn verify(&self, model_dir: &Path) -> Result<(), RSBertError> {
+        let mut expected = vec![
+            "modules.json".to_string(),
+            "config.json".to_string(),
+            "rust_model.ot".to_string(),
+        ];
+
+        // The tokenizer files depend on the transformer in config.json
+        let transformer_type = read_json(&model_dir.join("config.json"))
+            .ok()
+            .and_then(|config| config["model_type"].as_str().and_then(model_type))
+            .unwrap_or_else(|| transformer_type(self.model_type));
+        let (vocab, merges) = vocab_file_names(transformer_type);
+        expected.extend(std::iter::once(vocab).chain(merges).map(String::from));
+
+        if let Ok(modules) = read_json(&model_dir.join("modules.json")) {
+            for module in modules.as_array().into_iter().flatten() {
+                let path = module["path"].as_str().unwrap_or_default();
+                let module_type = module["type"].as_str().unwrap_or_default();
+                if module_type.ends_with("Pooling") || module_type.ends_with("Dense") {
+                    expected.push(format!("{path}/config.json"));
+                }
+                if module_type.ends_with("Dense") {
+                    expected.push(format!("{path}/rust_model.ot"));
+                }
+            }
+        }
+
+        let missing: Vec<String> = expected
+            .into_iter()
+            .filter(|file| !model_dir.join(file).is_file())
+            .collect();
+        if !missing.is_empty() {
+            return Err(RSBertError::MissingModelFiles {
+                model_dir: model_dir.to_path_buf(),
+                missing,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn build(self) -> Result<RSBertEmbeddings, RSBertError> {
+        let model_dir = self.model_dir()?;
+        self.verify(&model_dir)?;
+
+        let replicas = self.replicas_for(&model_dir);
+        if let Some(threads) = self.threads_per_replica {
+            tch::set_num_threads((replicas * threads) as i32);
+        }
+
+        let device = self.device.to_device();
+        let mut models = vec![];
+        let mut embeddings_size = 0;
+        for i in 0..replicas {
+            let model = SentenceEmbeddingsBuilder::local(&model_dir)
+                .with_device(device)
+                .create_model()?;
+            if i == 0 {
+                embeddings_size = RSBertEmbeddings::init_embeddings_size(&model)?;
+            }
+            models.push(Arc::new(Mutex::new(model)));
+        }
+        let model = models[0].clone();
+
+        // The first replica stays shared with the tokenizer of
+        // RsBertTextSplitter, replicas only hold their lock while encoding
+        // a batch
+        let replicas = models
+            .into_iter()
+            .map(|model| {
+                move || {
+                    move |texts: &[String]| -> Result<Vec<Vec<f32>>, RSBertError> {
+                        Ok(lock_model(&model).encode(texts)?)
+                    }
+                }
+            })
+            .collect();
+        let worker = EmbeddingWorker::spawn_pool(replicas, self.batch_size)?;
+
+        Ok(RSBertEmbeddings {
+            model,
+            worker,
+            max_sequence_length: read_max_seq_length(&model_dir),
+            model_dir,
+            device,
+            embeddings_size,
+        })
+    }
+}
+
+/// Size in bytes of the weights (`.ot` files) in a model directory and
+/// its module directories.
+fn model_weights_size(model_dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(model_dir) else {
+        return 0;
+    };
+
+    entries
+        .flatten()
+        .map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                model_weights_size(&path)
+            } else if path.extension().is_some_and(|e| e == "ot") {
+                entry.metadata().map(|m| m.len()).unwrap_or_default()
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+/// Name of the sentence-transformers model of a model type.
+fn model_type_name(model_type: SentenceEmbeddingsModelType) -> &'static str {
+    #[allow(unreachable_patterns)]
+    match model_type {
+        SentenceEmbeddingsModelType::AllMiniLmL12V2 => "all-MiniLM-L12-v2",
+        SentenceEmbeddingsModelType::AllMiniLmL6V2 => "all-MiniLM-L6-v2",
+        SentenceEmbeddingsModelType::AllDistilrobertaV1 => "all-distilroberta-v1",
+        SentenceEmbeddingsModelType::BertBaseNliMeanTokens => "bert-base-nli-mean-tokens",
+        SentenceEmbeddingsModelType::DistiluseBaseMultilingualCased => {
+            "distiluse-base-multilingual-cased"
+        }
+        SentenceEmbeddingsModelType::ParaphraseAlbertSmallV2 => "paraphrase-albert-small-v2",
+        SentenceEmbeddingsModelType::SentenceT5Base => "sentence-t5-base",
+        // Model types added upstream have no default name, use a model
+        // path or model name for them
+        _ => "unknown-model",
+    }
+}
+
+/// Transformer of a model type.
+fn transformer_type(model_type: SentenceEmbeddingsModelType) -> ModelType {
+    match model_type {
+        SentenceEmbeddingsModelType::AllDistilrobertaV1 => ModelType::Roberta,
+        SentenceEmbeddingsModelType::DistiluseBaseMultilingualCased => ModelType::DistilBert,
+        SentenceEmbeddingsModelType::ParaphraseAlbertSmallV2 => ModelType::Albert,
+        SentenceEmbeddingsModelType::SentenceT5Base => ModelType::T5,
+        _ => ModelType::Bert,
+    }
+}
+
 #[async_trait]
 impl traits::Embeddings for RSBertEmbeddings {
     type Error = RSBertError;
 
     async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
-        let encoded = self
-            .model
-            .lock()
-            .map_err(|e| RSBertError::MutexPoisonError(e.to_string()))?
-            .encode(texts.as_slice())?;
-
-        Ok(encoded)
+        self.worker.embed(texts).await
     }
 
     async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
-        let encoded = self
-            .model
-            .lock()
-            .map_err(|e| RSBertError::MutexPoisonError(e.to_string()))?
-            .encode(&[query])?
-            .get(0)
-            .cloned()
-            .ok_or(RSBertError::EmptyEmbeddings)?;
-
-        Ok(encoded)
+        self.worker
+            .embed(vec![query])
+            .await?
+            .pop()
+            .ok_or(RSBertError::EmptyEmbeddings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_model_dir_test() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("yt_buddy_models_{}", std::process::id()));
+        let builder = RSBertEmbeddingsBuilder::new(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+            .without_env_var()
+            .with_cache_dir(&cache_dir);
+        let model_dir = builder.model_dir().unwrap();
+        assert_eq!(model_dir, cache_dir.join("all-MiniLM-L12-v2"));
+
+        std::fs::create_dir_all(model_dir.join("1_Pooling")).unwrap();
+        std::fs::create_dir_all(model_dir.join("2_Dense")).unwrap();
+        for file in [
+            "config.json",
+            "rust_model.ot",
+            "vocab.txt",
+            "1_Pooling/config.json",
+            "2_Dense/config.json",
+        ] {
+            std::fs::write(model_dir.join(file), "{}").unwrap();
+        }
+        std::fs::write(
+            model_dir.join("modules.json"),
+            r#"[
+                {"path": "", "type": "sentence_transformers.models.Transformer"},
+                {"path": "1_Pooling", "type": "sentence_transformers.models.Pooling"},
+                {"path": "2_Dense", "type": "sentence_transformers.models.Dense"},
+                {"path": "3_Normalize", "type": "sentence_transformers.models.Normalize"}
+            ]"#,
+        )
+        .unwrap();
+
+        std::fs::write(model_dir.join("rust_model.ot"), vec![0u8; 1000]).unwrap();
+        let pool = builder.clone().with_replicas(8).with_memory_limit(3500);
+        assert_eq!(pool.replicas_for(&model_dir), 3);
+        assert_eq!(pool.with_memory_limit(10).replicas_for(&model_dir), 1);
+
+        let missing = match builder.verify(&model_dir) {
+            Err(RSBertError::MissingModelFiles { missing, .. }) => missing,
+            other => panic!("Unexpected verification result: {other:?}"),
+        };
+        std::fs::remove_dir_all(cache_dir).unwrap();
+        assert_eq!(missing, vec!["2_Dense/rust_model.ot"]);
+
+        assert!(matches!(
+            RSBertEmbeddingsBuilder::new(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+                .without_env_var()
+                .model_dir(),
+            Err(RSBertError::NoModelDir)
+        ));
+    }
+
+    #[test]
+    fn builder_model_dir_precedence_test() {
+        let env_var = format!("YT_BUDDY_MODEL_DIR_TEST_{}", std::process::id());
+        std::env::set_var(&env_var, "/models/from-env");
+        let builder = RSBertEmbeddingsBuilder::new(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+            .with_env_var(&env_var)
+            .with_cache_dir("resources");
+
+        assert_eq!(
+            builder.model_dir().unwrap(),
+            PathBuf::from("/models/from-env")
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .with_model_path("/models/explicit")
+                .model_dir()
+                .unwrap(),
+            PathBuf::from("/models/explicit")
+        );
+        std::env::remove_var(&env_var);
+        assert_eq!(
+            builder.model_dir().unwrap(),
+            PathBuf::from("resources/all-MiniLM-L12-v2")
+        );
     }
 }
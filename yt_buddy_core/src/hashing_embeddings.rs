@@ -0,0 +1,164 @@
+use async_trait::async_trait;
+use llm_chain::traits;
+use thiserror::Error;
+
+use crate::WordTokenizer;
+
+/// Embedding never fails, there is no model to load or run.
+#[derive(Debug, Error)]
+pub enum HashingEmbeddingsError {}
+
+impl traits::EmbeddingsError for HashingEmbeddingsError {}
+
+/// Deterministic embeddings without any model files, for tests and CI.
+///
+/// Texts are lowercased and cut into words (punctuation trimmed), and
+/// every word n-gram is hashed (FNV-1a) into one of `dimensions` buckets,
+/// adding or subtracting one depending on the hash. The vectors are L2
+/// normalised. Texts sharing words are close, texts sharing none are
+/// (nearly) orthogonal, and the same text always gets the same vector, on
+/// any machine.
+///
+/// Use it wherever `RSBertEmbeddings` is used, with `create_tokenizer` as
+/// the model tokenizer.
+#[derive(Debug, Clone)]
+pub struct HashingEmbeddings {
+    dimensions: usize,
+    min_ngram: usize,
+    max_ngram: usize,
+    max_sequence_length: usize,
+}
+
+impl Default for HashingEmbeddings {
+    fn default() -> Self {
+        Self {
+            dimensions: 384,
+            min_ngram: 1,
+            max_ngram: 2,
+            max_sequence_length: 256,
+        }
+    }
+}
+
+impl HashingEmbeddings {
+    /// 384 dimensions like `all-MiniLM-L12-v2`, words and word pairs, and
+    /// a max sequence length of 256 words.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = dimensions.max(1);
+        self
+    }
+
+    /// Hashes the n-grams of `min` to `max` words.
+    pub fn with_ngrams(mut self, min: usize, max: usize) -> Self {
+        self.min_ngram = min.max(1);
+        self.max_ngram = max.max(self.min_ngram);
+        self
+    }
+
+    /// Max number of words `create_tokenizer` lets chunks have. Embedding
+    /// itself never truncates.
+    pub fn with_max_sequence_length(mut self, max_sequence_length: usize) -> Self {
+        self.max_sequence_length = max_sequence_length;
+        self
+    }
+
+    pub fn get_embeddings_size(&self) -> u64 {
+        self.dimensions as u64
+    }
+
+    pub fn max_sequence_length(&self) -> Option<usize> {
+        Some(self.max_sequence_length)
+    }
+
+    pub fn create_tokenizer(&self) -> WordTokenizer {
+        WordTokenizer::new().with_max_seq_length(self.max_sequence_length)
+    }
+
+    pub fn embed(&self, text: &str) -> Vec<f32> {
+        let words: Vec<String> = text
+            .split_whitespace()
+            .map(|word| {
+                word.trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase()
+            })
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        let mut embedding = vec![0.0; self.dimensions];
+        for n in self.min_ngram..=self.max_ngram {
+            for ngram in words.windows(n) {
+                let hash = fnv1a(&ngram.join(" "));
+                let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+                embedding[(hash % self.dimensions as u64) as usize] += sign;
+            }
+        }
+
+        let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            embedding.iter_mut().for_each(|x| *x /= norm);
+        }
+
+        embedding
+    }
+}
+
+#[async_trait]
+impl traits::Embeddings for HashingEmbeddings {
+    type Error = HashingEmbeddingsError;
+
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+        Ok(texts.iter().map(|text| self.embed(text)).collect())
+    }
+
+    async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+        Ok(self.embed(&query))
+    }
+}
+
+/// 64-bit FNV-1a, stable across platforms and Rust versions unlike the
+/// std hashers.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use llm_chain::traits::Embeddings;
+
+    use super::*;
+
+    fn dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(a, b)| a * b).sum()
+    }
+
+    #[tokio::test]
+    async fn embed_test() {
+        let embeddings = HashingEmbeddings::new().with_dimensions(64);
+
+        let vectors = embeddings
+            .embed_texts(vec![
+                "The cat sleeps on the mat.".to_string(),
+                "the CAT sleeps on the mat".to_string(),
+                "A cat sleeps all day".to_string(),
+                "Rust compiles to native code".to_string(),
+                "".to_string(),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(vectors[0].len(), 64);
+        assert_eq!(vectors[0], vectors[1]);
+        assert!((dot(&vectors[0], &vectors[0]) - 1.0).abs() < 1e-5);
+        assert!(dot(&vectors[0], &vectors[2]) > dot(&vectors[0], &vectors[3]));
+        assert!(vectors[4].iter().all(|x| *x == 0.0));
+
+        // The same on every run and machine
+        assert_eq!(fnv1a("a"), 0xaf63dc4c8601ec8c);
+    }
+}
@@ -1,8 +1,14 @@
+mod cached_embeddings;
+mod embedding_worker;
 mod embeddings;
+mod hashing_embeddings;
 mod retrievers;
 mod text_splitter;
 
+pub use cached_embeddings::*;
+pub use embedding_worker::*;
 pub use embeddings::*;
+pub use hashing_embeddings::*;
 pub use retrievers::*;
 pub use text_splitter::*;
 
@@ -29,7 +29,13 @@ where
     E: Embeddings + Send + Sync,
     M: QdrantMetadata,
 {
-    // @TODO: impl new() ?
+    pub fn new(vector_store: Qdrant<E, M>) -> Self {
+        Self { vector_store }
+    }
+
+    pub fn get_vector_store(&self) -> &Qdrant<E, M> {
+        &self.vector_store
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use llm_chain::schema::Document;
+use llm_chain::tokens::TokenizerError;
+use llm_chain::TextSplitter;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use thiserror::Error;
+
+use super::ChunkingPolicyError;
+
+/// Why a document failed to split.
+#[derive(Debug, Error)]
+pub enum SplitError {
+    #[error(transparent)]
+    TokenizerError(#[from] TokenizerError),
+    /// A chunk doesn't fit the chunking policy, even re-split
+    #[error(transparent)]
+    ChunkingError(#[from] ChunkingPolicyError),
+}
+
+/// A document of a batch that failed to split.
+#[derive(Debug, Error)]
+#[error("Failed to split document {index}: {error}")]
+pub struct DocumentSplitError {
+    /// Index of the document in the batch
+    pub index: usize,
+    #[source]
+    pub error: SplitError,
+}
+
+/// Splits batches of documents in parallel with any `TextSplitter`.
+///
+/// Documents are spread across a thread pool (the global rayon pool,
+/// unless given one) and the results come back in the order of the batch,
+/// one per document: its chunks, or the error it failed with. A document
+/// failing doesn't stop the others from being split.
+pub struct BatchSplitter<S> {
+    splitter: S,
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl<S> BatchSplitter<S> {
+    pub fn new(splitter: S) -> Self {
+        Self {
+            splitter,
+            pool: None,
+        }
+    }
+
+    /// Splits on a dedicated pool of `num_threads` threads.
+    pub fn with_num_threads(mut self, num_threads: usize) -> Result<Self, ThreadPoolBuildError> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("batch-splitter-{i}"))
+            .build()?;
+        self.pool = Some(Arc::new(pool));
+        Ok(self)
+    }
+
+    pub fn with_thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn get_splitter(&self) -> &S {
+        &self.splitter
+    }
+
+    pub fn split_texts<TT, T>(
+        &self,
+        texts: &[T],
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Vec<Result<Vec<String>, DocumentSplitError>>
+    where
+        S: TextSplitter<TT> + Sync,
+        TT: Clone,
+        T: AsRef<str> + Sync,
+    {
+        self.install(|| {
+            texts
+                .par_iter()
+                .enumerate()
+                .map(|(index, text)| {
+                    self.splitter
+                        .split_text(text.as_ref(), max_tokens_per_chunk, chunk_overlap)
+                        .map_err(|error| DocumentSplitError {
+                            index,
+                            error: error.into(),
+                        })
+                })
+                .collect()
+        })
+    }
+
+    /// Splits every document into documents of its chunks, each with the
+    /// metadata of the document.
+    pub fn split_documents<TT, M>(
+        &self,
+        documents: &[Document<M>],
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Vec<Result<Vec<Document<M>>, DocumentSplitError>>
+    where
+        S: TextSplitter<TT> + Sync,
+        TT: Clone,
+        M: Clone + Send + Sync,
+    {
+        self.install(|| {
+            documents
+                .par_iter()
+                .enumerate()
+                .map(|(index, document)| {
+                    let chunks = self
+                        .splitter
+                        .split_text(&document.page_content, max_tokens_per_chunk, chunk_overlap)
+                        .map_err(|error| DocumentSplitError {
+                            index,
+                            error: error.into(),
+                        })?;
+
+                    Ok(chunks
+                        .into_iter()
+                        .map(|page_content| Document {
+                            page_content,
+                            metadata: document.metadata.clone(),
+                        })
+                        .collect())
+                })
+                .collect()
+        })
+    }
+
+    fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use llm_chain::tokens::Tokenizer;
+
+    use super::*;
+    use crate::RecursiveTextSplitter;
+
+    /// One token per whitespace separated word, failing on `!`.
+    struct StrictWordTokenizer;
+
+    impl Tokenizer<String> for StrictWordTokenizer {
+        fn tokenize_str(&self, doc: &str) -> Result<Vec<String>, TokenizerError> {
+            if doc.contains('!') {
+                return Err(TokenizerError::TokenizationError);
+            }
+            Ok(doc.split_whitespace().map(String::from).collect())
+        }
+
+        fn to_string(&self, tokens: Vec<String>) -> Result<String, TokenizerError> {
+            Ok(tokens.join(" "))
+        }
+    }
+
+    #[test]
+    fn split_documents_test() {
+        let documents: Vec<Document<Vec<(String, String)>>> = (0..20)
+            .map(|i| Document {
+                page_content: match i {
+                    7 => "no way!".to_string(),
+                    _ => format!("document {i} has five words"),
+                },
+                metadata: Some(vec![("index".to_string(), i.to_string())]),
+            })
+            .collect();
+
+        let results = BatchSplitter::new(RecursiveTextSplitter::new(StrictWordTokenizer))
+            .with_num_threads(4)
+            .unwrap()
+            .split_documents(&documents, 3, 0);
+
+        assert_eq!(results.len(), 20);
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(chunks) => {
+                    let texts: Vec<&str> = chunks.iter().map(|c| c.page_content.as_str()).collect();
+                    assert_eq!(
+                        texts,
+                        vec![format!("document {i} has"), "five words".to_string()]
+                    );
+                    assert_eq!(chunks[1].metadata, documents[i].metadata);
+                }
+                Err(e) => assert_eq!((i, e.index), (7, 7)),
+            }
+        }
+    }
+}
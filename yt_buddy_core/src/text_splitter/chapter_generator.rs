@@ -0,0 +1,363 @@
+use std::collections::{HashMap, HashSet};
+
+use llm_chain::traits::Embeddings;
+use sm_llm_loaders::{Chapter, ChapterSource, SegmentMerger, TimedParagraph, TimedSegment};
+use thiserror::Error;
+
+use super::similarity::{cosine_similarity, mean};
+
+#[rustfmt::skip]
+const STOP_WORDS: &[&str] = &[
+    // English
+    "about", "after", "again", "all", "also", "and", "any", "are", "because", "been", "before",
+    "but", "can", "could", "did", "does", "doing", "don't", "for", "from", "get", "going", "got",
+    "had", "has", "have", "here", "how", "i'm", "into", "it's", "its", "just", "know", "let",
+    "like", "more", "not", "now", "one", "only", "our", "out", "really", "right", "see", "she",
+    "should", "some", "that", "that's", "the", "their", "them", "then", "there", "these", "they",
+    "thing", "things", "this", "those", "very", "want", "was", "way", "well", "were", "what",
+    "when", "where", "which", "who", "why", "will", "with", "would", "yeah", "you", "your",
+    // Greek
+    "και", "είναι", "για", "από", "που", "δεν", "ένα", "μια", "ότι", "στο", "στη", "στην", "στα",
+    "στον", "στις", "στους", "της", "του", "των", "τον", "την", "τις", "τους", "αυτό", "αυτά",
+    "αυτή", "αλλά", "όπως", "εδώ", "εκεί", "πολύ", "όταν", "μας", "σας", "θέλω", "έχει", "έχουμε",
+    "ήταν", "οποίο", "οποία", "μπορεί", "πως", "πώς", "τώρα", "λοιπόν",
+];
+
+#[derive(Debug, Error)]
+pub enum ChapterGeneratorError<E> {
+    #[error("Embeddings error: {0}")]
+    EmbeddingsError(E),
+    #[error("Got {actual} embeddings for {expected} blocks")]
+    EmbeddingsCountMismatch { expected: usize, actual: usize },
+}
+
+/// Splits a timed transcript into chapters, for videos without any.
+///
+/// TextTiling with sentence embeddings: the transcript is merged into
+/// blocks of about `block_duration` seconds, every gap between blocks
+/// scored by the cosine similarity of the mean embeddings of the `window`
+/// blocks on each side, and chapters start at the gaps that are deepest
+/// valleys of similarity (depth over the mean minus half the standard
+/// deviation), no closer than `min_chapter_duration` seconds to each
+/// other or to either end.
+///
+/// Every chapter is labeled with its top `label_keywords` keywords by
+/// TF-IDF across chapters. Chapters are `ChapterSource::Generated`, with
+/// the same metadata as description chapters.
+pub struct ChapterGenerator<E> {
+    embeddings: E,
+    block_duration: f64,
+    window: usize,
+    min_chapter_duration: f64,
+    max_chapters: Option<usize>,
+    label_keywords: usize,
+    stop_words: HashSet<String>,
+}
+
+impl<E> ChapterGenerator<E>
+where
+    E: Embeddings,
+{
+    pub fn new(embeddings: E) -> Self {
+        Self {
+            embeddings,
+            block_duration: 20.0,
+            window: 2,
+            min_chapter_duration: 60.0,
+            max_chapters: None,
+            label_keywords: 3,
+            stop_words: STOP_WORDS.iter().map(|w| w.to_string()).collect(),
+        }
+    }
+
+    pub fn with_block_duration(mut self, seconds: f64) -> Self {
+        self.block_duration = seconds;
+        self
+    }
+
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    pub fn with_min_chapter_duration(mut self, seconds: f64) -> Self {
+        self.min_chapter_duration = seconds;
+        self
+    }
+
+    pub fn with_max_chapters(mut self, max_chapters: usize) -> Self {
+        self.max_chapters = Some(max_chapters.max(1));
+        self
+    }
+
+    pub fn with_label_keywords(mut self, label_keywords: usize) -> Self {
+        self.label_keywords = label_keywords.max(1);
+        self
+    }
+
+    pub fn with_stop_words(mut self, stop_words: &[&str]) -> Self {
+        self.stop_words
+            .extend(stop_words.iter().map(|w| w.to_lowercase()));
+        self
+    }
+
+    pub fn get_embeddings(&self) -> &E {
+        &self.embeddings
+    }
+
+    pub async fn generate(
+        &self,
+        segments: &[TimedSegment],
+    ) -> Result<Vec<Chapter>, ChapterGeneratorError<E::Error>> {
+        let blocks = SegmentMerger::new()
+            .with_pause_threshold(f64::INFINITY)
+            .with_sentence_pause(f64::INFINITY)
+            .with_target_duration(self.block_duration)
+            .with_max_duration(self.block_duration * 1.5)
+            .with_max_chars(usize::MAX)
+            .merge(segments);
+        if blocks.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let texts = blocks.iter().map(|block| block.text.clone()).collect();
+        let embeddings = self
+            .embeddings
+            .embed_texts(texts)
+            .await
+            .map_err(ChapterGeneratorError::EmbeddingsError)?;
+        if embeddings.len() != blocks.len() {
+            return Err(ChapterGeneratorError::EmbeddingsCountMismatch {
+                expected: blocks.len(),
+                actual: embeddings.len(),
+            });
+        }
+
+        // similarities[i] is the similarity at the gap after block i
+        let similarities: Vec<f32> = (1..blocks.len())
+            .map(|i| {
+                let before = mean(&embeddings[i.saturating_sub(self.window)..i]);
+                let after = mean(&embeddings[i..usize::min(i + self.window, embeddings.len())]);
+                cosine_similarity(&before, &after)
+            })
+            .collect();
+        let boundaries = self.boundaries(&blocks, &similarities);
+
+        let mut ranges = vec![];
+        let mut start = 0;
+        for boundary in boundaries {
+            ranges.push(start..boundary);
+            start = boundary;
+        }
+        ranges.push(start..blocks.len());
+
+        let texts: Vec<String> = ranges
+            .iter()
+            .map(|range| {
+                blocks[range.clone()]
+                    .iter()
+                    .map(|block| block.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        let labels = self.labels(&texts);
+
+        let ends: Vec<f64> = ranges
+            .iter()
+            .skip(1)
+            .map(|range| blocks[range.start].start)
+            .chain(std::iter::once(blocks[blocks.len() - 1].end))
+            .collect();
+
+        Ok(ranges
+            .into_iter()
+            .zip(ends)
+            .zip(labels)
+            .enumerate()
+            .map(|(index, ((range, end), title))| Chapter {
+                title,
+                // The first chapter covers the video from the start
+                start: if index == 0 {
+                    0.0
+                } else {
+                    blocks[range.start].start
+                },
+                end: Some(end),
+                source: ChapterSource::Generated,
+            })
+            .collect())
+    }
+
+    /// Indices of the blocks chapters start at, the first one excluded.
+    fn boundaries(&self, blocks: &[TimedParagraph], similarities: &[f32]) -> Vec<usize> {
+        let depths: Vec<f32> = (0..similarities.len())
+            .map(|gap| depth(similarities, gap))
+            .collect();
+        if depths.is_empty() {
+            return vec![];
+        }
+
+        let mean_depth = depths.iter().sum::<f32>() / depths.len() as f32;
+        let deviation = (depths.iter().map(|d| (d - mean_depth).powi(2)).sum::<f32>()
+            / depths.len() as f32)
+            .sqrt();
+        let cutoff = mean_depth - deviation / 2.0;
+
+        let mut candidates: Vec<usize> = (0..depths.len())
+            .filter(|gap| depths[*gap] > 0.0 && depths[*gap] > cutoff)
+            .collect();
+        candidates.sort_by(|a, b| depths[*b].total_cmp(&depths[*a]));
+
+        let video_end = blocks[blocks.len() - 1].end;
+        let max_boundaries = self.max_chapters.map(|m| m - 1).unwrap_or(usize::MAX);
+        let mut boundaries: Vec<usize> = vec![];
+        for gap in candidates {
+            if boundaries.len() >= max_boundaries {
+                break;
+            }
+
+            let time = blocks[gap + 1].start;
+            let far_enough = time >= self.min_chapter_duration
+                && video_end - time >= self.min_chapter_duration
+                && boundaries
+                    .iter()
+                    .all(|b| (blocks[*b].start - time).abs() >= self.min_chapter_duration);
+            if far_enough {
+                boundaries.push(gap + 1);
+            }
+        }
+        boundaries.sort_unstable();
+
+        boundaries
+    }
+
+    /// Top keywords of every text by TF-IDF across the texts.
+    fn labels(&self, texts: &[String]) -> Vec<String> {
+        let counts: Vec<HashMap<String, usize>> = texts
+            .iter()
+            .map(|text| {
+                let mut counts = HashMap::new();
+                for word in self.keywords(text) {
+                    *counts.entry(word).or_insert(0) += 1;
+                }
+                counts
+            })
+            .collect();
+
+        let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+        for word in counts.iter().flat_map(|c| c.keys()) {
+            *document_frequency.entry(word).or_insert(0) += 1;
+        }
+
+        counts
+            .iter()
+            .map(|counts| {
+                let mut scored: Vec<(&str, f32)> = counts
+                    .iter()
+                    .map(|(word, count)| {
+                        let idf = ((1 + texts.len()) as f32
+                            / (1 + document_frequency[word.as_str()]) as f32)
+                            .ln()
+                            + 1.0;
+                        (word.as_str(), *count as f32 * idf)
+                    })
+                    .collect();
+                scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+
+                let label = scored
+                    .iter()
+                    .take(self.label_keywords)
+                    .map(|(word, _)| *word)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                capitalize(&label)
+            })
+            .collect()
+    }
+
+    fn keywords<'a>(&'a self, text: &'a str) -> impl Iterator<Item = String> + 'a {
+        text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .map(|word| word.trim_matches('\'').to_lowercase())
+            .filter(|word| {
+                word.chars().count() >= 3
+                    && !word.chars().all(|c| c.is_numeric())
+                    && !self.stop_words.contains(word)
+            })
+    }
+}
+
+/// TextTiling depth of the similarity valley at `gap`: how far it is
+/// below the highest similarities reached climbing from it on each side.
+fn depth(similarities: &[f32], gap: usize) -> f32 {
+    let mut left = gap;
+    while left > 0 && similarities[left - 1] >= similarities[left] {
+        left -= 1;
+    }
+    let mut right = gap;
+    while right + 1 < similarities.len() && similarities[right + 1] >= similarities[right] {
+        right += 1;
+    }
+
+    (similarities[left] - similarities[gap]) + (similarities[right] - similarities[gap])
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashingEmbeddings;
+
+    #[tokio::test]
+    async fn generate_test() {
+        let segments: Vec<TimedSegment> = [
+            "my cat sleeps all day, the cat food is gone",
+            "the cat eats fish, cat food is fish",
+            "a cat purrs softly after the cat food",
+            "rust compiles fast code, rust code is safe",
+            "rust code has no garbage collector",
+            "rust traits make rust code great",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, text)| TimedSegment {
+            text: text.to_string(),
+            start: i as f64 * 10.0,
+            duration: 10.0,
+        })
+        .collect();
+
+        let chapters = ChapterGenerator::new(HashingEmbeddings::new())
+            .with_block_duration(10.0)
+            .with_window(1)
+            .with_min_chapter_duration(20.0)
+            .generate(&segments)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            chapters,
+            vec![
+                Chapter {
+                    title: "Cat, food, fish".to_string(),
+                    start: 0.0,
+                    end: Some(30.0),
+                    source: ChapterSource::Generated,
+                },
+                Chapter {
+                    title: "Rust, code, collector".to_string(),
+                    start: 30.0,
+                    end: Some(60.0),
+                    source: ChapterSource::Generated,
+                },
+            ]
+        );
+    }
+}
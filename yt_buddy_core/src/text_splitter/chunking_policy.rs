@@ -0,0 +1,183 @@
+use llm_chain::tokens::TokenizerError;
+
+use super::ModelTokenizer;
+
+/// Tokens the model adds around every sequence (`[CLS]`/`[SEP]`,
+/// `<s>`/`</s>`), which count against its max sequence length.
+pub const SPECIAL_TOKENS_PER_SEQUENCE: usize = 2;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ChunkingPolicyError {
+    #[error("Invalid chunking policy: {0}")]
+    InvalidPolicy(String),
+    #[error(
+        "Chunks of up to {hard_max_tokens} tokens don't fit the max sequence length ({max_sequence_length}) of the model"
+    )]
+    ExceedsModel {
+        hard_max_tokens: usize,
+        max_sequence_length: usize,
+    },
+    #[error("Chunk of {tokens} tokens is over the hard max of {hard_max_tokens} tokens")]
+    ChunkTooLong {
+        tokens: usize,
+        hard_max_tokens: usize,
+    },
+}
+
+/// How long chunks should be, in tokens of the embedding model (special
+/// tokens excluded).
+///
+/// Chunks are split at `target_tokens`, consecutive chunks sharing
+/// `overlap_tokens`. `hard_max_tokens` is the length no chunk may go
+/// over, so that no chunk gets truncated when embedded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkingPolicy {
+    pub target_tokens: usize,
+    pub overlap_tokens: usize,
+    pub hard_max_tokens: usize,
+}
+
+impl ChunkingPolicy {
+    pub fn new(
+        target_tokens: usize,
+        overlap_tokens: usize,
+        hard_max_tokens: usize,
+    ) -> Result<Self, ChunkingPolicyError> {
+        if target_tokens == 0 {
+            return Err(ChunkingPolicyError::InvalidPolicy(
+                "target_tokens must be positive".to_string(),
+            ));
+        }
+        if overlap_tokens >= target_tokens {
+            return Err(ChunkingPolicyError::InvalidPolicy(format!(
+                "overlap_tokens ({overlap_tokens}) must be less than target_tokens ({target_tokens})"
+            )));
+        }
+        if target_tokens > hard_max_tokens {
+            return Err(ChunkingPolicyError::InvalidPolicy(format!(
+                "target_tokens ({target_tokens}) must not be over hard_max_tokens ({hard_max_tokens})"
+            )));
+        }
+
+        Ok(Self {
+            target_tokens,
+            overlap_tokens,
+            hard_max_tokens,
+        })
+    }
+
+    /// Chunks a model with `max_sequence_length` embeds whole, with an
+    /// eighth of them overlapping. They are split a sixteenth short of the
+    /// hard max, chunks tokenizing a bit longer on their own still fit.
+    pub fn for_max_sequence_length(
+        max_sequence_length: usize,
+    ) -> Result<Self, ChunkingPolicyError> {
+        let hard_max_tokens = max_sequence_length.saturating_sub(SPECIAL_TOKENS_PER_SEQUENCE);
+        let target_tokens = hard_max_tokens - hard_max_tokens / 16;
+
+        Self::new(target_tokens, target_tokens / 8, hard_max_tokens)
+    }
+
+    /// Checks that chunks of this policy fit a model with
+    /// `max_sequence_length`.
+    pub fn validate_for(&self, max_sequence_length: usize) -> Result<(), ChunkingPolicyError> {
+        if self.hard_max_tokens + SPECIAL_TOKENS_PER_SEQUENCE > max_sequence_length {
+            return Err(ChunkingPolicyError::ExceedsModel {
+                hard_max_tokens: self.hard_max_tokens,
+                max_sequence_length,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks a chunk of `tokens` tokens against the hard max.
+    pub fn check_chunk(&self, tokens: usize) -> Result<(), ChunkingPolicyError> {
+        if tokens > self.hard_max_tokens {
+            return Err(ChunkingPolicyError::ChunkTooLong {
+                tokens,
+                hard_max_tokens: self.hard_max_tokens,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Re-splits a chunk over the hard max into pieces that fit, splitting
+    /// pieces still over it at half the length, and so on. A chunk that
+    /// fits is returned as is.
+    pub fn fit_chunk<S: ModelTokenizer>(
+        &self,
+        tokenizer: &S,
+        chunk: String,
+    ) -> Result<Vec<String>, TokenizerError> {
+        let mut pieces = vec![];
+        self.fit_into(tokenizer, chunk, self.target_tokens, &mut pieces)?;
+
+        Ok(pieces)
+    }
+
+    fn fit_into<S: ModelTokenizer>(
+        &self,
+        tokenizer: &S,
+        chunk: String,
+        max_tokens: usize,
+        pieces: &mut Vec<String>,
+    ) -> Result<(), TokenizerError> {
+        if max_tokens == 0 || tokenizer.count_tokens(&chunk) <= self.hard_max_tokens {
+            pieces.push(chunk);
+            return Ok(());
+        }
+
+        for piece in tokenizer.split_text(&chunk, max_tokens, 0)? {
+            self.fit_into(tokenizer, piece, max_tokens / 2, pieces)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WordTokenizer;
+
+    #[test]
+    fn policy_test() {
+        let policy = ChunkingPolicy::for_max_sequence_length(128).unwrap();
+        assert_eq!(policy, ChunkingPolicy::new(119, 14, 126).unwrap());
+        assert!(policy.validate_for(128).is_ok());
+        assert!(policy.check_chunk(127).is_err());
+
+        assert!(ChunkingPolicy::new(100, 100, 120).is_err());
+        assert!(ChunkingPolicy::new(130, 10, 120).is_err());
+        assert_eq!(
+            ChunkingPolicy::new(100, 10, 127).unwrap().validate_for(128),
+            Err(ChunkingPolicyError::ExceedsModel {
+                hard_max_tokens: 127,
+                max_sequence_length: 128,
+            })
+        );
+    }
+
+    #[test]
+    fn fit_chunk_test() {
+        let policy = ChunkingPolicy::for_max_sequence_length(34).unwrap();
+        assert!(policy.target_tokens < policy.hard_max_tokens);
+        let tokenizer = WordTokenizer::new().with_max_seq_length(34);
+
+        let words: Vec<String> = (0..70).map(|i| format!("w{i}")).collect();
+        let fitting = words[..32].join(" ");
+        assert_eq!(
+            policy.fit_chunk(&tokenizer, fitting.clone()).unwrap(),
+            vec![fitting]
+        );
+
+        let pieces = policy.fit_chunk(&tokenizer, words.join(" ")).unwrap();
+        assert_eq!(pieces.len(), 3);
+        for piece in pieces.iter() {
+            assert!(policy.check_chunk(tokenizer.count_tokens(piece)).is_ok());
+        }
+        assert_eq!(pieces.join(" "), words.join(" "));
+    }
+}
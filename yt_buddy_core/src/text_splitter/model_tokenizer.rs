@@ -0,0 +1,22 @@
+use llm_chain::TextSplitter;
+
+/// The tokenizer of an embedding model, counting tokens the way the model
+/// does so that chunks can be checked to fit in it.
+pub trait ModelTokenizer: TextSplitter<Self::TokenType> + Send + Sync {
+    type TokenType: Clone;
+
+    /// Max number of tokens (special tokens included) the model embeds,
+    /// when known.
+    fn max_seq_length(&self) -> Option<usize>;
+
+    /// Number of tokens of `text`, special tokens excluded.
+    fn count_tokens(&self, text: &str) -> usize;
+
+    /// Number of tokens of each text.
+    fn count_tokens_list<S: AsRef<str> + Sync>(&self, texts: &[S]) -> Vec<usize> {
+        texts
+            .iter()
+            .map(|text| self.count_tokens(text.as_ref()))
+            .collect()
+    }
+}
@@ -0,0 +1,254 @@
+use llm_chain::tokens::{Tokenizer, TokenizerError};
+use llm_chain::TextSplitter;
+
+/// Paragraph breaks, line breaks, sentence ends, spaces, then characters.
+const DEFAULT_SEPARATORS: &[&str] = &["\n\n", "\n", ". ", "? ", "! ", "; ", " ", ""];
+
+/// Splits text on the coarsest separator that gives pieces within the
+/// token budget.
+///
+/// The text is split on the first separator of the list it contains, and
+/// every piece still over the budget is split again with the separators
+/// after it, down to single characters for the empty separator. Pieces
+/// are then merged back into chunks of up to the budget, consecutive
+/// chunks sharing up to the overlap in whole pieces.
+///
+/// With `keep_separator` the separators stay at the end of the piece
+/// before them (a sentence keeps its period), otherwise they are dropped
+/// and pieces merged into a chunk are joined with the separator they were
+/// split on. Token counts of merged chunks are the sums of the counts of
+/// their pieces.
+#[derive(Clone)]
+pub struct RecursiveTextSplitter<T> {
+    tokenizer: T,
+    separators: Vec<String>,
+    keep_separator: bool,
+}
+
+impl<T> RecursiveTextSplitter<T> {
+    pub fn new(tokenizer: T) -> Self {
+        Self {
+            tokenizer,
+            separators: DEFAULT_SEPARATORS.iter().map(|s| s.to_string()).collect(),
+            keep_separator: true,
+        }
+    }
+
+    pub fn with_separators<S: Into<String>>(mut self, separators: Vec<S>) -> Self {
+        self.separators = separators.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_keep_separator(mut self, keep_separator: bool) -> Self {
+        self.keep_separator = keep_separator;
+        self
+    }
+
+    pub fn get_tokenizer(&self) -> &T {
+        &self.tokenizer
+    }
+
+    pub fn get_separators(&self) -> &[String] {
+        &self.separators
+    }
+
+    fn count_tokens<TT>(&self, text: &str) -> Result<usize, TokenizerError>
+    where
+        T: Tokenizer<TT>,
+        TT: Clone,
+    {
+        Ok(self.tokenizer.tokenize_str(text)?.len())
+    }
+
+    fn split_recursive<TT>(
+        &self,
+        text: &str,
+        separators: &[String],
+        max_tokens: usize,
+        overlap: usize,
+    ) -> Result<Vec<String>, TokenizerError>
+    where
+        T: Tokenizer<TT>,
+        TT: Clone,
+    {
+        let position = separators
+            .iter()
+            .position(|s| s.is_empty() || text.contains(s.as_str()));
+        let (separator, rest) = match position {
+            Some(i) => (separators[i].as_str(), &separators[i + 1..]),
+            None => ("", &separators[separators.len()..]),
+        };
+        let joiner = if self.keep_separator { "" } else { separator };
+
+        let mut chunks = vec![];
+        let mut pieces = vec![];
+        for piece in split_on(text, separator, self.keep_separator) {
+            if piece.trim().is_empty() {
+                continue;
+            }
+
+            let tokens = self.count_tokens(piece)?;
+            if tokens <= max_tokens {
+                pieces.push((piece, tokens));
+                continue;
+            }
+
+            chunks.extend(self.merge(&pieces, joiner, max_tokens, overlap)?);
+            pieces.clear();
+            if rest.is_empty() {
+                chunks.push(piece.trim().to_string());
+            } else {
+                chunks.extend(self.split_recursive(piece, rest, max_tokens, overlap)?);
+            }
+        }
+        chunks.extend(self.merge(&pieces, joiner, max_tokens, overlap)?);
+
+        Ok(chunks)
+    }
+
+    /// Packs pieces of up to `max_tokens` tokens into chunks.
+    fn merge<TT>(
+        &self,
+        pieces: &[(&str, usize)],
+        joiner: &str,
+        max_tokens: usize,
+        overlap: usize,
+    ) -> Result<Vec<String>, TokenizerError>
+    where
+        T: Tokenizer<TT>,
+        TT: Clone,
+    {
+        let joiner_tokens = self.count_tokens(joiner)?;
+        let joined_tokens = |pieces: &[(&str, usize)]| {
+            pieces.iter().map(|(_, tokens)| tokens).sum::<usize>()
+                + joiner_tokens * pieces.len().saturating_sub(1)
+        };
+
+        let mut chunks = vec![];
+        let mut start = 0;
+        while start < pieces.len() {
+            let mut end = start + 1;
+            while end < pieces.len() && joined_tokens(&pieces[start..=end]) <= max_tokens {
+                end += 1;
+            }
+            let chunk: Vec<&str> = pieces[start..end].iter().map(|(piece, _)| *piece).collect();
+            chunks.push(chunk.join(joiner).trim().to_string());
+
+            if end == pieces.len() {
+                break;
+            }
+
+            // Step back over the pieces that fit the overlap and still
+            // leave room for the next piece, always moving forward
+            let chunk_start = start;
+            start = end;
+            while start - 1 > chunk_start
+                && joined_tokens(&pieces[start - 1..end]) <= overlap
+                && joined_tokens(&pieces[start - 1..=end]) <= max_tokens
+            {
+                start -= 1;
+            }
+        }
+
+        Ok(chunks)
+    }
+}
+
+/// Splits `text` on `separator`, the empty separator splitting it into
+/// characters. Kept separators end the piece before them.
+fn split_on<'a>(text: &'a str, separator: &str, keep_separator: bool) -> Vec<&'a str> {
+    if separator.is_empty() {
+        return text
+            .char_indices()
+            .map(|(i, c)| &text[i..i + c.len_utf8()])
+            .collect();
+    }
+
+    if keep_separator {
+        text.split_inclusive(separator).collect()
+    } else {
+        text.split(separator).collect()
+    }
+}
+
+impl<T, TT> Tokenizer<TT> for RecursiveTextSplitter<T>
+where
+    T: Tokenizer<TT>,
+    TT: Clone,
+{
+    fn tokenize_str(&self, doc: &str) -> Result<Vec<TT>, TokenizerError> {
+        self.tokenizer.tokenize_str(doc)
+    }
+
+    fn to_string(&self, tokens: Vec<TT>) -> Result<String, TokenizerError> {
+        self.tokenizer.to_string(tokens)
+    }
+}
+
+impl<T, TT> TextSplitter<TT> for RecursiveTextSplitter<T>
+where
+    T: Tokenizer<TT>,
+    TT: Clone,
+{
+    fn split_text(
+        &self,
+        doc: &str,
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Result<Vec<String>, TokenizerError> {
+        self.split_recursive(
+            doc,
+            &self.separators,
+            max_tokens_per_chunk.max(1),
+            chunk_overlap,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WordTokenizer;
+
+    #[test]
+    fn split_text_test() {
+        let text =
+            "# Tokenizers\n\nThey split text. Into many pieces.\nEach piece has an id\n\nThe end";
+        let splitter = RecursiveTextSplitter::new(WordTokenizer::new());
+
+        assert_eq!(
+            splitter.split_text(text, 8, 0).unwrap(),
+            vec![
+                "# Tokenizers",
+                "They split text. Into many pieces.",
+                "Each piece has an id",
+                "The end",
+            ]
+        );
+        assert_eq!(
+            splitter.split_text(text, 4, 0).unwrap(),
+            vec![
+                "# Tokenizers",
+                "They split text.",
+                "Into many pieces.",
+                "Each piece has an",
+                "id",
+                "The end",
+            ]
+        );
+        assert_eq!(
+            splitter.split_text("a b c d e", 3, 1).unwrap(),
+            vec!["a b c", "c d e"]
+        );
+
+        let splitter = RecursiveTextSplitter::new(WordTokenizer::new())
+            .with_separators(vec![". ", " "])
+            .with_keep_separator(false);
+        assert_eq!(
+            splitter
+                .split_text("They split text. Into many pieces.", 3, 0)
+                .unwrap(),
+            vec!["They split text", "Into many pieces."]
+        );
+    }
+}
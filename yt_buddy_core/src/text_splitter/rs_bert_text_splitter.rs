@@ -0,0 +1,90 @@
+use std::sync::{Arc, Mutex};
+
+use llm_chain::tokens::{Tokenizer, TokenizerError};
+use llm_chain::TextSplitter;
+use rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel;
+
+use super::rs_bert_tokenizer::{split_spans, token_spans, TokenType};
+use crate::TokenSpan;
+
+/// Splits text on the tokenizer of a sentence embeddings model.
+///
+/// Tokens are counted without special tokens (`[CLS]`, `[SEP]`, ...)
+/// and without truncating to the max sequence length of the model, so
+/// a chunk of `n` tokens embeds as `n` tokens plus the special ones.
+/// `split_text` cuts the original text at token boundaries, chunks are
+/// exact substrings of it rather than decoded (and e.g. lowercased)
+/// tokens.
+///
+/// Tokenizing locks the model, `RsBertTokenizer` splits the same way
+/// without getting in the way of embedding.
+pub struct RsBertTextSplitter {
+    model: Arc<Mutex<SentenceEmbeddingsModel>>,
+}
+
+impl RsBertTextSplitter {
+    pub fn new(model: Arc<Mutex<SentenceEmbeddingsModel>>) -> Self {
+        Self { model }
+    }
+
+    /// Tokenizes `text`, keeping the position of every token.
+    pub fn tokenize_with_offsets(&self, text: &str) -> Result<Vec<TokenSpan>, TokenizerError> {
+        let model = self
+            .model
+            .lock()
+            .map_err(|_e| TokenizerError::TokenizationError)?;
+
+        Ok(token_spans(model.get_tokenizer(), text))
+    }
+
+    /// Number of tokens of `text`, special tokens excluded.
+    pub fn count_tokens(&self, text: &str) -> Result<usize, TokenizerError> {
+        let model = self
+            .model
+            .lock()
+            .map_err(|_e| TokenizerError::TokenizationError)?;
+
+        Ok(model.get_tokenizer().tokenize(text).len())
+    }
+}
+
+impl Tokenizer<TokenType> for RsBertTextSplitter {
+    fn tokenize_str(&self, doc: &str) -> Result<Vec<TokenType>, TokenizerError> {
+        let model = self
+            .model
+            .lock()
+            .map_err(|_e| TokenizerError::TokenizerCreationError)?;
+
+        let tokenizer = model.get_tokenizer();
+        Ok(tokenizer.convert_tokens_to_ids(&tokenizer.tokenize(doc)))
+    }
+
+    /// Decodes token ids. Decoding is lossy for uncased models and word
+    /// piece spacing, use `split_text` to get back the original text.
+    fn to_string(&self, tokens: Vec<TokenType>) -> Result<String, TokenizerError> {
+        let model = self
+            .model
+            .lock()
+            .map_err(|_e| TokenizerError::ToStringError)?;
+
+        Ok(model.get_tokenizer().decode(&tokens, true, true))
+    }
+}
+
+impl TextSplitter<TokenType> for RsBertTextSplitter {
+    fn split_text(
+        &self,
+        doc: &str,
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Result<Vec<String>, TokenizerError> {
+        let spans = self.tokenize_with_offsets(doc)?;
+
+        Ok(split_spans(
+            doc,
+            &spans,
+            max_tokens_per_chunk,
+            chunk_overlap,
+        ))
+    }
+}
@@ -0,0 +1,361 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use llm_chain::tokens::{Tokenizer, TokenizerError};
+use llm_chain::TextSplitter;
+use rust_bert::pipelines::common::{ModelType, TokenizerOption};
+
+use super::ModelTokenizer;
+use crate::RSBertError;
+
+pub(crate) type TokenType = i64;
+
+/// A token of a text and where it came from in that text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenSpan {
+    pub id: TokenType,
+    pub token: String,
+    /// Byte range of the token in the tokenized text
+    pub range: Range<usize>,
+}
+
+/// The tokenizer of a sentence embeddings model, loaded on its own from
+/// the vocabulary files of the model directory.
+///
+/// Unlike `RsBertTextSplitter` it doesn't share the model, so splitting
+/// never waits on (or blocks) embedding. It is cheap to clone and can be
+/// used from any number of threads at once.
+///
+/// Counting and splitting work the same as with `RsBertTextSplitter`: no
+/// special tokens, no truncation and chunks that are exact substrings of
+/// the split text.
+#[derive(Clone)]
+pub struct RsBertTokenizer {
+    tokenizer: Arc<TokenizerOption>,
+    max_seq_length: Option<usize>,
+}
+
+impl RsBertTokenizer {
+    /// Loads the tokenizer of the sentence-transformers model in
+    /// `model_dir`, using `config.json` for the model type,
+    /// `sentence_bert_config.json`/`tokenizer_config.json` for the casing
+    /// and max sequence length, and the `vocab.txt`, `vocab.json` +
+    /// `merges.txt` or SentencePiece model found next to them.
+    pub fn from_model_dir<P: AsRef<Path>>(model_dir: P) -> Result<Self, RSBertError> {
+        let model_dir = model_dir.as_ref();
+
+        let config = read_json(&model_dir.join("config.json"))?;
+        let sentence_bert_config =
+            read_json(&model_dir.join("sentence_bert_config.json")).unwrap_or_default();
+        let tokenizer_config =
+            read_json(&model_dir.join("tokenizer_config.json")).unwrap_or_default();
+
+        let model_type_name = config["model_type"].as_str().unwrap_or_default();
+        let model_type = model_type(model_type_name).ok_or_else(|| {
+            RSBertError::ModelError(format!("Unsupported model type: {model_type_name:?}"))
+        })?;
+
+        let lower_case = sentence_bert_config["do_lower_case"]
+            .as_bool()
+            .or_else(|| tokenizer_config["do_lower_case"].as_bool())
+            .unwrap_or(false);
+        let strip_accents = tokenizer_config["strip_accents"].as_bool();
+
+        let (vocab_path, merges_path) = vocab_files(model_dir, model_type)?;
+        let tokenizer = TokenizerOption::from_file(
+            model_type,
+            &path_str(&vocab_path)?,
+            merges_path.as_deref().map(path_str).transpose()?.as_deref(),
+            lower_case,
+            strip_accents,
+            None,
+        )?;
+
+        Ok(Self {
+            tokenizer: Arc::new(tokenizer),
+            max_seq_length: read_max_seq_length(model_dir),
+        })
+    }
+
+    pub fn from_tokenizer(tokenizer: TokenizerOption) -> Self {
+        Self {
+            tokenizer: Arc::new(tokenizer),
+            max_seq_length: None,
+        }
+    }
+
+    pub fn with_max_seq_length(mut self, max_seq_length: usize) -> Self {
+        self.max_seq_length = Some(max_seq_length);
+        self
+    }
+
+    /// Max number of tokens (special tokens included) the model embeds,
+    /// when the model directory tells.
+    pub fn max_seq_length(&self) -> Option<usize> {
+        self.max_seq_length
+    }
+
+    pub fn get_tokenizer(&self) -> &TokenizerOption {
+        &self.tokenizer
+    }
+
+    /// Tokenizes `text`, keeping the position of every token.
+    pub fn tokenize_with_offsets(&self, text: &str) -> Vec<TokenSpan> {
+        token_spans(&self.tokenizer, text)
+    }
+
+    /// Number of tokens of `text`, special tokens excluded.
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.tokenizer.tokenize(text).len()
+    }
+
+    /// Number of tokens of each text, tokenizing them in parallel.
+    pub fn count_tokens_list<S: AsRef<str> + Sync>(&self, texts: &[S]) -> Vec<usize> {
+        self.tokenizer
+            .tokenize_list(texts)
+            .iter()
+            .map(|tokens| tokens.len())
+            .collect()
+    }
+}
+
+impl Tokenizer<TokenType> for RsBertTokenizer {
+    fn tokenize_str(&self, doc: &str) -> Result<Vec<TokenType>, TokenizerError> {
+        Ok(self
+            .tokenizer
+            .convert_tokens_to_ids(&self.tokenizer.tokenize(doc)))
+    }
+
+    /// Decodes token ids. Decoding is lossy for uncased models and word
+    /// piece spacing, use `split_text` to get back the original text.
+    fn to_string(&self, tokens: Vec<TokenType>) -> Result<String, TokenizerError> {
+        Ok(self.tokenizer.decode(&tokens, true, true))
+    }
+}
+
+impl TextSplitter<TokenType> for RsBertTokenizer {
+    fn split_text(
+        &self,
+        doc: &str,
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Result<Vec<String>, TokenizerError> {
+        let spans = self.tokenize_with_offsets(doc);
+
+        Ok(split_spans(
+            doc,
+            &spans,
+            max_tokens_per_chunk,
+            chunk_overlap,
+        ))
+    }
+}
+
+impl ModelTokenizer for RsBertTokenizer {
+    type TokenType = TokenType;
+
+    fn max_seq_length(&self) -> Option<usize> {
+        self.max_seq_length
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        RsBertTokenizer::count_tokens(self, text)
+    }
+
+    fn count_tokens_list<S: AsRef<str> + Sync>(&self, texts: &[S]) -> Vec<usize> {
+        RsBertTokenizer::count_tokens_list(self, texts)
+    }
+}
+
+pub(crate) fn read_json(path: &Path) -> Result<serde_json::Value, RSBertError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| RSBertError::ModelError(format!("{}: {e}", path.display())))?;
+
+    serde_json::from_str(&content)
+        .map_err(|e| RSBertError::ModelError(format!("{}: {e}", path.display())))
+}
+
+/// Max sequence length of the model in `model_dir`: the
+/// sentence-transformers `max_seq_length`, else the tokenizer's
+/// `model_max_length`, else the position embeddings of the model.
+pub(crate) fn read_max_seq_length(model_dir: &Path) -> Option<usize> {
+    let from = |file: &str, key: &str| {
+        read_json(&model_dir.join(file))
+            .ok()
+            .and_then(|config| config[key].as_u64())
+    };
+
+    from("sentence_bert_config.json", "max_seq_length")
+        // Tokenizers without a limit use a huge sentinel value
+        .or_else(|| from("tokenizer_config.json", "model_max_length").filter(|l| *l < 1 << 20))
+        .or_else(|| from("config.json", "max_position_embeddings"))
+        .map(|l| l as usize)
+}
+
+fn path_str(path: &Path) -> Result<String, RSBertError> {
+    path.to_str()
+        .map(String::from)
+        .ok_or_else(|| RSBertError::ModelError(format!("Invalid path: {}", path.display())))
+}
+
+pub(crate) fn model_type(name: &str) -> Option<ModelType> {
+    match name {
+        "bert" => Some(ModelType::Bert),
+        "distilbert" => Some(ModelType::DistilBert),
+        "roberta" => Some(ModelType::Roberta),
+        "xlm-roberta" => Some(ModelType::XLMRoberta),
+        "albert" => Some(ModelType::Albert),
+        "t5" => Some(ModelType::T5),
+        "mpnet" => Some(ModelType::MPNet),
+        _ => None,
+    }
+}
+
+/// Names of the vocabulary (and merges, for BPE) files of a model type.
+pub(crate) fn vocab_file_names(model_type: ModelType) -> (&'static str, Option<&'static str>) {
+    match model_type {
+        ModelType::Roberta => ("vocab.json", Some("merges.txt")),
+        ModelType::XLMRoberta => ("sentencepiece.bpe.model", None),
+        ModelType::Albert | ModelType::T5 => ("spiece.model", None),
+        _ => ("vocab.txt", None),
+    }
+}
+
+/// Vocabulary (and merges, for BPE) files of a model type.
+fn vocab_files(
+    model_dir: &Path,
+    model_type: ModelType,
+) -> Result<(PathBuf, Option<PathBuf>), RSBertError> {
+    let (vocab, merges) = vocab_file_names(model_type);
+
+    let vocab = model_dir.join(vocab);
+    let merges = merges.map(|m| model_dir.join(m));
+    for file in std::iter::once(&vocab).chain(merges.iter()) {
+        if !file.is_file() {
+            return Err(RSBertError::ModelError(format!(
+                "Missing tokenizer file: {}",
+                file.display()
+            )));
+        }
+    }
+
+    Ok((vocab, merges))
+}
+
+/// Tokenizes `text` without special tokens or truncation, mapping the
+/// (char based) token offsets to byte ranges of `text`.
+pub(crate) fn token_spans(tokenizer: &TokenizerOption, text: &str) -> Vec<TokenSpan> {
+    let tokenized = tokenizer.tokenize_with_offsets(text);
+    let ids = tokenizer.convert_tokens_to_ids(&tokenized.tokens);
+
+    // Byte index of every char index, plus the end of the text
+    let byte_indices: Vec<usize> = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let byte_index = |char_index: u32| {
+        byte_indices
+            .get(char_index as usize)
+            .copied()
+            .unwrap_or(text.len())
+    };
+
+    let mut spans: Vec<TokenSpan> = Vec::with_capacity(ids.len());
+    for ((id, token), offset) in ids.into_iter().zip(tokenized.tokens).zip(tokenized.offsets) {
+        // Tokens without an offset don't cover any text, pin them to
+        // the end of the previous token
+        let range = match offset {
+            Some(offset) => byte_index(offset.begin)..byte_index(offset.end),
+            None => {
+                let end = spans.last().map(|s| s.range.end).unwrap_or_default();
+                end..end
+            }
+        };
+        spans.push(TokenSpan { id, token, range });
+    }
+
+    spans
+}
+
+/// Cuts `text` into windows of at most `max_tokens` tokens, consecutive
+/// windows sharing `overlap` tokens. Every window is the slice of `text`
+/// from its first to its last token.
+pub(crate) fn split_spans(
+    text: &str,
+    spans: &[TokenSpan],
+    max_tokens: usize,
+    overlap: usize,
+) -> Vec<String> {
+    let max_tokens = max_tokens.max(1);
+    let step = max_tokens.saturating_sub(overlap).max(1);
+
+    let mut chunks = vec![];
+    let mut start = 0;
+    while start < spans.len() {
+        let end = usize::min(start + max_tokens, spans.len());
+        let range = spans[start].range.start..spans[end - 1].range.end;
+        chunks.push(text[range].to_string());
+
+        if end == spans.len() {
+            break;
+        }
+        start += step;
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A model directory with a tiny BERT vocabulary.
+    fn test_model_dir(name: &str) -> PathBuf {
+        let vocab = [
+            "[PAD]", "[UNK]", "[CLS]", "[SEP]", "[MASK]", "hello", "world", "token", "##izer",
+            "##s", "split", "text", ",", "!", "ε", "##να",
+        ];
+        let dir = std::env::temp_dir().join(format!("yt_buddy_{name}_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("vocab.txt"), vocab.join("\n")).unwrap();
+        std::fs::write(dir.join("config.json"), r#"{"model_type": "bert"}"#).unwrap();
+        std::fs::write(
+            dir.join("sentence_bert_config.json"),
+            r#"{"max_seq_length": 128, "do_lower_case": true}"#,
+        )
+        .unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn token_spans_test() {
+        let dir = test_model_dir("token_spans");
+        let tokenizer = RsBertTokenizer::from_model_dir(&dir).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        let text = "Hello,  Tokenizers split ένα text!";
+
+        let spans = tokenizer.tokenize_with_offsets(text);
+
+        assert_eq!(tokenizer.max_seq_length(), Some(128));
+        let ids: Vec<TokenType> = spans.iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec![5, 12, 7, 8, 9, 10, 14, 15, 11, 13]);
+        let tokens: Vec<&str> = spans.iter().map(|s| &text[s.range.clone()]).collect();
+        assert_eq!(
+            tokens,
+            vec!["Hello", ",", "Token", "izer", "s", "split", "έ", "να", "text", "!"]
+        );
+        assert_eq!(
+            tokenizer.count_tokens_list(&[text, "hello world"]),
+            vec![10, 2]
+        );
+
+        assert_eq!(
+            tokenizer.split_text(text, 4, 1).unwrap(),
+            vec!["Hello,  Tokenizer", "izers split έ", "ένα text!"]
+        );
+        assert_eq!(tokenizer.split_text(text, 100, 10).unwrap(), vec![text]);
+    }
+}
@@ -0,0 +1,249 @@
+use std::ops::Range;
+
+use llm_chain::tokens::{Tokenizer, TokenizerError};
+use llm_chain::traits::Embeddings;
+use thiserror::Error;
+
+use super::similarity::{cosine_similarity, mean};
+use super::{SentenceSegmenter, SentenceSplitter};
+
+#[derive(Debug, Error)]
+pub enum SemanticSplitterError<E> {
+    #[error(transparent)]
+    TokenizerError(#[from] TokenizerError),
+    #[error("Embeddings error: {0}")]
+    EmbeddingsError(E),
+    #[error("Got {actual} embeddings for {expected} sentences")]
+    EmbeddingsCountMismatch { expected: usize, actual: usize },
+}
+
+/// Cosine distance between neighbouring windows above which a new chunk
+/// starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakpointThreshold {
+    /// The given percentile (0 to 100) of the distances in the text
+    Percentile(f64),
+    /// A fixed distance
+    Absolute(f32),
+}
+
+/// Splits text into topic-coherent chunks.
+///
+/// Every sentence is embedded, and a new chunk starts between two
+/// sentences wherever the cosine distance between the mean embeddings of
+/// the `window` sentences before and the `window` sentences after goes
+/// above the threshold. Chunks still over `max_tokens` are split again at
+/// their largest distance, sentences over it on their own are cut at
+/// word boundaries.
+pub struct SemanticSplitter<E, T> {
+    embeddings: E,
+    sentence_splitter: SentenceSplitter<T>,
+    threshold: BreakpointThreshold,
+    window: usize,
+    max_tokens: usize,
+}
+
+impl<E, T> SemanticSplitter<E, T>
+where
+    E: Embeddings,
+{
+    pub fn new(embeddings: E, tokenizer: T, max_tokens: usize) -> Self {
+        Self {
+            embeddings,
+            sentence_splitter: SentenceSplitter::new(tokenizer),
+            threshold: BreakpointThreshold::Percentile(95.0),
+            window: 1,
+            max_tokens: max_tokens.max(1),
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: BreakpointThreshold) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Number of sentences on each side of a candidate breakpoint whose
+    /// embeddings are compared.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    pub fn with_segmenter(mut self, segmenter: SentenceSegmenter) -> Self {
+        self.sentence_splitter = self.sentence_splitter.with_segmenter(segmenter);
+        self
+    }
+
+    pub fn get_embeddings(&self) -> &E {
+        &self.embeddings
+    }
+
+    pub async fn split<TT>(
+        &self,
+        text: &str,
+    ) -> Result<Vec<String>, SemanticSplitterError<E::Error>>
+    where
+        T: Tokenizer<TT>,
+        TT: Clone,
+    {
+        let sentences = self.sentence_splitter.sentences(text, self.max_tokens)?;
+        if sentences.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let texts = sentences
+            .iter()
+            .map(|(range, _)| text[range.clone()].to_string())
+            .collect();
+        let embeddings = self
+            .embeddings
+            .embed_texts(texts)
+            .await
+            .map_err(SemanticSplitterError::EmbeddingsError)?;
+        if embeddings.len() != sentences.len() {
+            return Err(SemanticSplitterError::EmbeddingsCountMismatch {
+                expected: sentences.len(),
+                actual: embeddings.len(),
+            });
+        }
+
+        // distances[i] is the distance between sentences i and i + 1
+        let distances: Vec<f32> = (1..sentences.len())
+            .map(|i| {
+                let before = mean(&embeddings[i.saturating_sub(self.window)..i]);
+                let after = mean(&embeddings[i..usize::min(i + self.window, embeddings.len())]);
+                1.0 - cosine_similarity(&before, &after)
+            })
+            .collect();
+        let threshold = match self.threshold {
+            BreakpointThreshold::Percentile(p) => percentile(&distances, p),
+            BreakpointThreshold::Absolute(t) => t,
+        };
+
+        let mut groups = vec![];
+        let mut start = 0;
+        for (i, distance) in distances.iter().enumerate() {
+            if *distance > threshold {
+                groups.push(start..i + 1);
+                start = i + 1;
+            }
+        }
+        groups.push(start..sentences.len());
+
+        let mut chunks = vec![];
+        for group in groups {
+            for range in self.fit(&sentences, &distances, group) {
+                chunks.push(
+                    text[sentences[range.start].0.start..sentences[range.end - 1].0.end]
+                        .to_string(),
+                );
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// Splits a group of sentences over `max_tokens` at its largest
+    /// distance, until every part fits.
+    fn fit(
+        &self,
+        sentences: &[(Range<usize>, usize)],
+        distances: &[f32],
+        group: Range<usize>,
+    ) -> Vec<Range<usize>> {
+        let tokens: usize = sentences[group.clone()].iter().map(|(_, t)| t).sum();
+        if tokens <= self.max_tokens || group.len() == 1 {
+            return vec![group];
+        }
+
+        let split = (group.start..group.end - 1)
+            .max_by(|a, b| distances[*a].total_cmp(&distances[*b]))
+            .map(|i| i + 1)
+            .unwrap_or(group.end);
+
+        let mut parts = self.fit(sentences, distances, group.start..split);
+        parts.extend(self.fit(sentences, distances, split..group.end));
+        parts
+    }
+}
+
+/// Linearly interpolated `p`-th percentile, or infinity (no breakpoint)
+/// without values.
+fn percentile(values: &[f32], p: f64) -> f32 {
+    if values.is_empty() {
+        return f32::INFINITY;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let rank = p.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use llm_chain::traits::EmbeddingsError;
+
+    use super::*;
+    use crate::WordTokenizer;
+
+    #[derive(Debug, Error)]
+    #[error("Topic embeddings error")]
+    struct TopicEmbeddingsError;
+
+    impl EmbeddingsError for TopicEmbeddingsError {}
+
+    /// Embeds texts about cats and texts about anything else apart.
+    struct TopicEmbeddings;
+
+    #[async_trait]
+    impl Embeddings for TopicEmbeddings {
+        type Error = TopicEmbeddingsError;
+
+        async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+            Ok(texts
+                .iter()
+                .map(|t| match t.contains("cat") {
+                    true => vec![1.0, 0.1],
+                    false => vec![0.1, 1.0],
+                })
+                .collect())
+        }
+
+        async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+            Ok(self.embed_texts(vec![query]).await?.remove(0))
+        }
+    }
+
+    #[tokio::test]
+    async fn split_test() {
+        let text = "My cat sleeps. The cat eats. Rust is fast. It has no GC. A cat again.";
+
+        let splitter = SemanticSplitter::new(TopicEmbeddings, WordTokenizer::new(), 100)
+            .with_threshold(BreakpointThreshold::Absolute(0.5));
+        assert_eq!(
+            splitter.split(text).await.unwrap(),
+            vec![
+                "My cat sleeps. The cat eats.",
+                "Rust is fast. It has no GC.",
+                "A cat again.",
+            ]
+        );
+
+        let splitter = SemanticSplitter::new(TopicEmbeddings, WordTokenizer::new(), 4)
+            .with_threshold(BreakpointThreshold::Percentile(100.0));
+        assert_eq!(
+            splitter.split(text).await.unwrap(),
+            vec![
+                "My cat sleeps.",
+                "The cat eats.",
+                "Rust is fast.",
+                "It has no GC.",
+                "A cat again.",
+            ]
+        );
+    }
+}
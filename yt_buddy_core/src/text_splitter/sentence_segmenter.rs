@@ -0,0 +1,306 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// Abbreviations (lowercase, without the final period) a period doesn't
+/// end a sentence after.
+const ENGLISH_ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "vs", "e.g", "i.e", "a.m", "p.m", "u.s",
+    "inc", "ltd", "corp", "fig", "vol", "approx", "dept", "jan", "feb", "apr", "jun", "jul", "aug",
+    "sept", "oct", "nov", "dec",
+];
+
+const GREEK_ABBREVIATIONS: &[&str] = &[
+    "κ", "κα", "δρ", "καθ", "π.χ", "δηλ", "κλπ", "κ.λπ", "κτλ", "κ.τ.λ", "βλ", "σελ", "αρ", "τηλ",
+    "μ.χ", "λ.χ", "οδ", "υπ", "σημ", "χλμ",
+];
+
+/// Abbreviations that are also words ending sentences ("The answer was
+/// no."), only abbreviations when followed by a digit or a lowercase word.
+const AMBIGUOUS_ABBREVIATIONS: &[&str] = &["no", "co", "est", "mar", "sep", "etc", "εκ"];
+
+/// Default number of words after which unpunctuated text (e.g. ASR
+/// captions) is cut into pseudo-sentences.
+const DEFAULT_MAX_SENTENCE_WORDS: usize = 40;
+
+const CLOSING_PUNCTUATION: &[char] = &['"', '\'', '”', '’', '»', ')', ']'];
+
+/// Splits text into sentences, for English and Greek text.
+///
+/// Sentences end at `.`, `!`, `?`, `…` and the Greek question mark (`;`
+/// in a sentence with Greek letters, or U+037E) followed by whitespace and a word that
+/// doesn't start in lowercase, and at blank lines. Periods after known
+/// abbreviations (`Dr.`, `e.g.`, `π.χ.`, ...) or single letter initials
+/// other than `I` don't end a sentence, nor do periods after ambiguous
+/// abbreviations (`No.`, `etc.`, ...) followed by a digit.
+///
+/// Text without punctuation, like auto-generated captions, is cut at
+/// line breaks and then every `max_sentence_words` words.
+#[derive(Debug, Clone)]
+pub struct SentenceSegmenter {
+    pub abbreviations: HashSet<String>,
+    /// Words that are abbreviations only when followed by a digit or a
+    /// lowercase word
+    pub ambiguous_abbreviations: HashSet<String>,
+    pub max_sentence_words: usize,
+}
+
+impl SentenceSegmenter {
+    pub fn new() -> Self {
+        Self {
+            abbreviations: ENGLISH_ABBREVIATIONS
+                .iter()
+                .chain(GREEK_ABBREVIATIONS)
+                .map(|a| a.to_string())
+                .collect(),
+            ambiguous_abbreviations: AMBIGUOUS_ABBREVIATIONS
+                .iter()
+                .map(|a| a.to_string())
+                .collect(),
+            max_sentence_words: DEFAULT_MAX_SENTENCE_WORDS,
+        }
+    }
+
+    pub fn with_abbreviations(mut self, abbreviations: &[&str]) -> Self {
+        self.abbreviations.extend(
+            abbreviations
+                .iter()
+                .map(|a| a.trim_end_matches('.').to_lowercase()),
+        );
+        self
+    }
+
+    pub fn with_max_sentence_words(mut self, max_sentence_words: usize) -> Self {
+        self.max_sentence_words = max_sentence_words.max(1);
+        self
+    }
+
+    /// Byte ranges of the sentences of `text`, without surrounding
+    /// whitespace.
+    pub fn segment(&self, text: &str) -> Vec<Range<usize>> {
+        let mut sentences = vec![];
+        let mut start = 0;
+
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let (index, c) = chars[i];
+
+            if c == '\n' && is_blank_line(&chars, i) {
+                push_trimmed(text, start..index, &mut sentences);
+                start = index;
+            } else if self.is_terminal(text, &chars, i) {
+                // Take in the whole run of punctuation and closing quotes
+                let mut end = i + 1;
+                while end < chars.len()
+                    && (is_terminal_char(chars[end].1)
+                        || CLOSING_PUNCTUATION.contains(&chars[end].1))
+                {
+                    end += 1;
+                }
+                let end_index = chars.get(end).map(|(i, _)| *i).unwrap_or(text.len());
+
+                let next_starts_sentence = match chars.get(end) {
+                    None => true,
+                    Some((_, c)) if c.is_whitespace() => next_non_space(&chars, end)
+                        .map(|c| !c.is_lowercase())
+                        .unwrap_or(true),
+                    Some(_) => false,
+                };
+                if next_starts_sentence {
+                    push_trimmed(text, start..end_index, &mut sentences);
+                    start = end_index;
+                }
+                i = end;
+                continue;
+            }
+
+            i += 1;
+        }
+        push_trimmed(text, start..text.len(), &mut sentences);
+
+        sentences
+            .into_iter()
+            .flat_map(|range| self.split_long(text, range))
+            .collect()
+    }
+
+    /// The sentences of `text`.
+    pub fn sentences<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        self.segment(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    fn is_terminal(&self, text: &str, chars: &[(usize, char)], i: usize) -> bool {
+        let (index, c) = chars[i];
+
+        match c {
+            '!' | '?' | '…' | '\u{037e}' => true,
+            // `;` is the question mark of Greek text
+            ';' => chars[..i]
+                .iter()
+                .rev()
+                .take_while(|(_, c)| !is_terminal_char(*c))
+                .any(|(_, c)| is_greek(*c)),
+            '.' => {
+                let word_start = text[..index]
+                    .rfind(|c: char| c.is_whitespace() || c == '(' || c == '"')
+                    .map(|i| i + 1)
+                    .unwrap_or(0);
+                let word = text[word_start..index].to_lowercase();
+                // "I." ends sentences ("So did I.")
+                let is_initial = word.chars().count() == 1
+                    && word != "i"
+                    && word.chars().all(|c| c.is_alphabetic())
+                    && text[word_start..index].chars().all(|c| c.is_uppercase());
+                let is_ambiguous_abbreviation = self.ambiguous_abbreviations.contains(&word)
+                    && next_non_space(chars, i + 1)
+                        .is_some_and(|c| c.is_ascii_digit() || c.is_lowercase());
+
+                !(self.abbreviations.contains(&word) || is_initial || is_ambiguous_abbreviation)
+            }
+            _ => false,
+        }
+    }
+
+    /// Cuts sentences over `max_sentence_words` words at line breaks,
+    /// then every `max_sentence_words` words.
+    fn split_long(&self, text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+        let sentence = &text[range.clone()];
+        if sentence.split_whitespace().count() <= self.max_sentence_words {
+            return vec![range];
+        }
+
+        let mut pieces = vec![];
+        let mut line_start = range.start;
+        for line in sentence.split_inclusive('\n') {
+            let line_range = line_start..line_start + line.len();
+            line_start = line_range.end;
+
+            let mut words = word_ranges(text, line_range).into_iter().peekable();
+            while words.peek().is_some() {
+                let chunk: Vec<Range<usize>> =
+                    words.by_ref().take(self.max_sentence_words).collect();
+                pieces.push(chunk[0].start..chunk[chunk.len() - 1].end);
+            }
+        }
+
+        pieces
+    }
+}
+
+impl Default for SentenceSegmenter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_terminal_char(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '…' | '\u{037e}')
+}
+
+fn is_greek(c: char) -> bool {
+    matches!(c, '\u{0370}'..='\u{03ff}' | '\u{1f00}'..='\u{1fff}')
+}
+
+fn next_non_space(chars: &[(usize, char)], from: usize) -> Option<char> {
+    chars[from.min(chars.len())..]
+        .iter()
+        .map(|(_, c)| *c)
+        .find(|c| !c.is_whitespace())
+}
+
+/// Whether the line break at `i` is followed by an empty line.
+fn is_blank_line(chars: &[(usize, char)], i: usize) -> bool {
+    chars[i + 1..]
+        .iter()
+        .take_while(|(_, c)| c.is_whitespace())
+        .any(|(_, c)| *c == '\n')
+}
+
+fn push_trimmed(text: &str, range: Range<usize>, sentences: &mut Vec<Range<usize>>) {
+    let slice = &text[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.end - (slice.len() - slice.trim_end().len());
+
+    if start < end {
+        sentences.push(start..end);
+    }
+}
+
+/// Byte ranges of the whitespace separated words in `range` of `text`.
+pub(super) fn word_ranges(text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut words = vec![];
+    let mut word_start = None;
+
+    for (i, c) in text[range.clone()].char_indices() {
+        let index = range.start + i;
+        match (c.is_whitespace(), word_start) {
+            (false, None) => word_start = Some(index),
+            (true, Some(start)) => {
+                words.push(start..index);
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = word_start {
+        words.push(start..range.end);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_test() {
+        let segmenter = SentenceSegmenter::new();
+
+        assert_eq!(
+            segmenter.sentences(
+                "Dr. Smith met J. Doe at 3.30 p.m. yesterday. Did it go well?! \"Yes.\" It did, e.g. on time…"
+            ),
+            vec![
+                "Dr. Smith met J. Doe at 3.30 p.m. yesterday.",
+                "Did it go well?!",
+                "\"Yes.\"",
+                "It did, e.g. on time…",
+            ]
+        );
+        assert_eq!(
+            segmenter.sentences("Τι κάνεις; Καλά, π.χ. σήμερα. Ο κ. Παπάς ήρθε.\n\nΝέα παράγραφος"),
+            vec![
+                "Τι κάνεις;",
+                "Καλά, π.χ. σήμερα.",
+                "Ο κ. Παπάς ήρθε.",
+                "Νέα παράγραφος",
+            ]
+        );
+        assert_eq!(
+            segmenter.sentences("first; second. Third"),
+            vec!["first; second.", "Third"]
+        );
+
+        assert_eq!(
+            segmenter
+                .sentences("See No. 5, est. 1990. The answer was no. So did I. Then etc. Now."),
+            vec![
+                "See No. 5, est. 1990.",
+                "The answer was no.",
+                "So did I.",
+                "Then etc.",
+                "Now.",
+            ]
+        );
+
+        let asr = SentenceSegmenter::new().with_max_sentence_words(3);
+        assert_eq!(
+            asr.sentences("so today we look\nat tokenizers and splitters"),
+            vec!["so today we", "look", "at tokenizers and", "splitters"]
+        );
+    }
+}
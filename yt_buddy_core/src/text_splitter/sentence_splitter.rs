@@ -0,0 +1,198 @@
+use std::ops::Range;
+
+use llm_chain::tokens::{Tokenizer, TokenizerError};
+use llm_chain::TextSplitter;
+
+use super::sentence_segmenter::word_ranges;
+use super::SentenceSegmenter;
+
+/// Splits text into chunks of whole sentences.
+///
+/// Sentences are packed into a chunk until the next one would go over the
+/// token budget, counting tokens with the wrapped `Tokenizer`. Overlap is
+/// by whole sentences too: a chunk starts with as many of the last
+/// sentences of the previous chunk as fit the overlap budget.
+///
+/// A sentence over the budget on its own is cut at word boundaries (a
+/// single word over the budget is kept whole). Chunks are exact
+/// substrings of the split text.
+#[derive(Clone)]
+pub struct SentenceSplitter<T> {
+    tokenizer: T,
+    segmenter: SentenceSegmenter,
+}
+
+impl<T> SentenceSplitter<T> {
+    pub fn new(tokenizer: T) -> Self {
+        Self {
+            tokenizer,
+            segmenter: SentenceSegmenter::new(),
+        }
+    }
+
+    pub fn with_segmenter(mut self, segmenter: SentenceSegmenter) -> Self {
+        self.segmenter = segmenter;
+        self
+    }
+
+    pub fn get_tokenizer(&self) -> &T {
+        &self.tokenizer
+    }
+
+    pub fn get_segmenter(&self) -> &SentenceSegmenter {
+        &self.segmenter
+    }
+
+    fn count_tokens<TT>(&self, text: &str) -> Result<usize, TokenizerError>
+    where
+        T: Tokenizer<TT>,
+        TT: Clone,
+    {
+        Ok(self.tokenizer.tokenize_str(text)?.len())
+    }
+
+    /// The sentences of `text` with their token counts, sentences over
+    /// `max_tokens` cut into pieces at word boundaries.
+    pub(super) fn sentences<TT>(
+        &self,
+        text: &str,
+        max_tokens: usize,
+    ) -> Result<Vec<(Range<usize>, usize)>, TokenizerError>
+    where
+        T: Tokenizer<TT>,
+        TT: Clone,
+    {
+        let mut sentences = vec![];
+
+        for range in self.segmenter.segment(text) {
+            let tokens = self.count_tokens(&text[range.clone()])?;
+            if tokens <= max_tokens {
+                sentences.push((range, tokens));
+                continue;
+            }
+
+            let mut piece: Option<(Range<usize>, usize)> = None;
+            for word_range in word_ranges(text, range) {
+                let word_tokens = self.count_tokens(&text[word_range.clone()])?;
+
+                piece = match piece {
+                    Some((piece_range, piece_tokens))
+                        if piece_tokens + word_tokens <= max_tokens =>
+                    {
+                        Some((
+                            piece_range.start..word_range.end,
+                            piece_tokens + word_tokens,
+                        ))
+                    }
+                    Some(full) => {
+                        sentences.push(full);
+                        Some((word_range, word_tokens))
+                    }
+                    None => Some((word_range, word_tokens)),
+                };
+            }
+            sentences.extend(piece);
+        }
+
+        Ok(sentences)
+    }
+}
+
+impl<T, TT> Tokenizer<TT> for SentenceSplitter<T>
+where
+    T: Tokenizer<TT>,
+    TT: Clone,
+{
+    fn tokenize_str(&self, doc: &str) -> Result<Vec<TT>, TokenizerError> {
+        self.tokenizer.tokenize_str(doc)
+    }
+
+    fn to_string(&self, tokens: Vec<TT>) -> Result<String, TokenizerError> {
+        self.tokenizer.to_string(tokens)
+    }
+}
+
+impl<T, TT> TextSplitter<TT> for SentenceSplitter<T>
+where
+    T: Tokenizer<TT>,
+    TT: Clone,
+{
+    /// Splits `doc` into chunks of whole sentences of at most
+    /// `max_tokens_per_chunk` tokens, repeating up to `chunk_overlap`
+    /// tokens of whole sentences from the end of the previous chunk.
+    fn split_text(
+        &self,
+        doc: &str,
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Result<Vec<String>, TokenizerError> {
+        let max_tokens = max_tokens_per_chunk.max(1);
+        let sentences = self.sentences(doc, max_tokens)?;
+
+        let mut chunks = vec![];
+        let mut start = 0;
+        while start < sentences.len() {
+            let mut end = start;
+            let mut tokens = 0;
+            while end < sentences.len() && (end == start || tokens + sentences[end].1 <= max_tokens)
+            {
+                tokens += sentences[end].1;
+                end += 1;
+            }
+            chunks.push(doc[sentences[start].0.start..sentences[end - 1].0.end].to_string());
+
+            if end == sentences.len() {
+                break;
+            }
+
+            // Step back over the sentences that fit the overlap and still
+            // leave room for the next sentence, always moving forward
+            let chunk_start = start;
+            let next = sentences[end].1;
+            let mut overlap = 0;
+            start = end;
+            while start - 1 > chunk_start
+                && overlap + sentences[start - 1].1 <= chunk_overlap
+                && overlap + sentences[start - 1].1 + next <= max_tokens
+            {
+                overlap += sentences[start - 1].1;
+                start -= 1;
+            }
+        }
+
+        Ok(chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WordTokenizer;
+
+    #[test]
+    fn split_text_test() {
+        let splitter = SentenceSplitter::new(WordTokenizer::new());
+        let text = "Τι είναι ο tokenizer; Σπάει το κείμενο. Ο κ. Παπάς το εξηγεί καλά. Τέλος.";
+
+        assert_eq!(
+            splitter.split_text(text, 7, 0).unwrap(),
+            vec![
+                "Τι είναι ο tokenizer; Σπάει το κείμενο.",
+                "Ο κ. Παπάς το εξηγεί καλά. Τέλος.",
+            ]
+        );
+        assert_eq!(
+            splitter.split_text(text, 10, 3).unwrap(),
+            vec![
+                "Τι είναι ο tokenizer; Σπάει το κείμενο.",
+                "Σπάει το κείμενο. Ο κ. Παπάς το εξηγεί καλά. Τέλος.",
+            ]
+        );
+        assert_eq!(
+            splitter
+                .split_text("one two three four five. Six.", 2, 0)
+                .unwrap(),
+            vec!["one two", "three four", "five. Six."]
+        );
+    }
+}
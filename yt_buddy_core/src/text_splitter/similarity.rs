@@ -0,0 +1,22 @@
+/// Element-wise mean of `vectors`, empty without vectors.
+pub(crate) fn mean(vectors: &[Vec<f32>]) -> Vec<f32> {
+    let mut mean = vec![0.0; vectors.first().map(|v| v.len()).unwrap_or_default()];
+    for vector in vectors {
+        for (m, v) in mean.iter_mut().zip(vector) {
+            *m += v / vectors.len() as f32;
+        }
+    }
+
+    mean
+}
+
+/// Cosine similarity of `a` and `b`, 0 if either is a zero vector.
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    match norm(a) * norm(b) {
+        n if n > 0.0 => dot / n,
+        _ => 0.0,
+    }
+}
@@ -0,0 +1,254 @@
+use std::ops::Range;
+
+use llm_chain::tokens::{Tokenizer, TokenizerError};
+use sm_llm_loaders::{TimedParagraph, TimedSegment};
+
+/// How much consecutive chunks of timed segments share.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimedOverlap {
+    /// Up to this many tokens of whole segments
+    Tokens(usize),
+    /// The segments starting in the last this many seconds of the
+    /// previous chunk
+    Seconds(f64),
+}
+
+/// A piece of a timed segment: the whole segment, or part of one that is
+/// over the token budget on its own.
+struct TimedPiece {
+    text: Range<usize>,
+    segment: usize,
+    start: f64,
+    end: f64,
+    tokens: usize,
+}
+
+/// Splits timed caption segments into chunks of up to a token budget,
+/// keeping the time span and the segment indices every chunk covers.
+///
+/// Chunks are made of whole segments joined by a space. A segment over
+/// the budget on its own is cut at word boundaries, its time span shared
+/// among the pieces by length.
+///
+/// The chunks are `TimedParagraph`s, so their `metadata()` has
+/// `start_seconds`, `end_seconds` and `segment_start`/`segment_end`.
+#[derive(Clone)]
+pub struct TimeAlignedSplitter<T> {
+    tokenizer: T,
+    max_tokens: usize,
+    overlap: TimedOverlap,
+}
+
+impl<T> TimeAlignedSplitter<T> {
+    pub fn new(tokenizer: T, max_tokens: usize) -> Self {
+        Self {
+            tokenizer,
+            max_tokens: max_tokens.max(1),
+            overlap: TimedOverlap::Tokens(0),
+        }
+    }
+
+    pub fn with_overlap(mut self, overlap: TimedOverlap) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    pub fn get_tokenizer(&self) -> &T {
+        &self.tokenizer
+    }
+
+    pub fn split<TT>(
+        &self,
+        segments: &[TimedSegment],
+    ) -> Result<Vec<TimedParagraph>, TokenizerError>
+    where
+        T: Tokenizer<TT>,
+        TT: Clone,
+    {
+        let pieces = self.pieces(segments)?;
+
+        let mut chunks = vec![];
+        let mut start = 0;
+        while start < pieces.len() {
+            let mut end = start;
+            let mut tokens = 0;
+            while end < pieces.len()
+                && (end == start || tokens + pieces[end].tokens <= self.max_tokens)
+            {
+                tokens += pieces[end].tokens;
+                end += 1;
+            }
+            chunks.push(chunk(segments, &pieces[start..end]));
+
+            if end == pieces.len() {
+                break;
+            }
+
+            // Step back over the pieces in the overlap that still leave
+            // room for the next piece, always moving forward
+            let chunk_start = start;
+            let chunk_end = pieces[end - 1].end;
+            let next = pieces[end].tokens;
+            let mut overlap = 0;
+            start = end;
+            while start - 1 > chunk_start {
+                let piece = &pieces[start - 1];
+                let in_overlap = match self.overlap {
+                    TimedOverlap::Tokens(tokens) => overlap + piece.tokens <= tokens,
+                    TimedOverlap::Seconds(seconds) => chunk_end - piece.start <= seconds,
+                };
+                if !in_overlap || overlap + piece.tokens + next > self.max_tokens {
+                    break;
+                }
+                overlap += piece.tokens;
+                start -= 1;
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// The non-empty segments with their token counts, segments over the
+    /// budget cut into pieces.
+    fn pieces<TT>(&self, segments: &[TimedSegment]) -> Result<Vec<TimedPiece>, TokenizerError>
+    where
+        T: Tokenizer<TT>,
+        TT: Clone,
+    {
+        let mut pieces = vec![];
+
+        for (index, segment) in segments.iter().enumerate() {
+            let text = segment.text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            let offset = segment.text.len() - segment.text.trim_start().len();
+
+            let tokens = self.tokenizer.tokenize_str(text)?.len();
+            if tokens <= self.max_tokens {
+                pieces.push(TimedPiece {
+                    text: offset..offset + text.len(),
+                    segment: index,
+                    start: segment.start,
+                    end: segment.end(),
+                    tokens,
+                });
+                continue;
+            }
+
+            // Word ranges packed into pieces of up to the budget
+            let mut ranges: Vec<(Range<usize>, usize)> = vec![];
+            let mut word_start = None;
+            for (i, c) in text
+                .char_indices()
+                .chain(std::iter::once((text.len(), ' ')))
+            {
+                match (c.is_whitespace(), word_start) {
+                    (false, None) => word_start = Some(i),
+                    (true, Some(start)) => {
+                        let word_tokens = self.tokenizer.tokenize_str(&text[start..i])?.len();
+                        match ranges.last_mut() {
+                            Some((range, tokens)) if *tokens + word_tokens <= self.max_tokens => {
+                                range.end = i;
+                                *tokens += word_tokens;
+                            }
+                            _ => ranges.push((start..i, word_tokens)),
+                        }
+                        word_start = None;
+                    }
+                    _ => {}
+                }
+            }
+
+            let seconds_per_byte = segment.duration / text.len() as f64;
+            for (range, tokens) in ranges {
+                pieces.push(TimedPiece {
+                    start: segment.start + range.start as f64 * seconds_per_byte,
+                    end: segment.start + range.end as f64 * seconds_per_byte,
+                    text: offset + range.start..offset + range.end,
+                    segment: index,
+                    tokens,
+                });
+            }
+        }
+
+        Ok(pieces)
+    }
+}
+
+fn chunk(segments: &[TimedSegment], pieces: &[TimedPiece]) -> TimedParagraph {
+    let text = pieces
+        .iter()
+        .map(|piece| &segments[piece.segment].text[piece.text.clone()])
+        .collect::<Vec<_>>()
+        .join(" ");
+    let first = &pieces[0];
+    let last = &pieces[pieces.len() - 1];
+
+    TimedParagraph {
+        text,
+        start: first.start,
+        end: pieces.iter().map(|p| p.end).fold(last.end, f64::max),
+        segment_range: first.segment..last.segment + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WordTokenizer;
+
+    fn segment(text: &str, start: f64, duration: f64) -> TimedSegment {
+        TimedSegment {
+            text: text.to_string(),
+            start,
+            duration,
+        }
+    }
+
+    #[test]
+    fn split_test() {
+        let segments = vec![
+            segment("today we look", 0.0, 2.0),
+            segment(" ", 2.0, 1.0),
+            segment("at tokenizers", 3.0, 2.0),
+            segment("they split text", 5.0, 2.0),
+            segment("into pieces", 7.0, 2.0),
+        ];
+
+        let chunks = TimeAlignedSplitter::new(WordTokenizer::new(), 5)
+            .split(&segments)
+            .unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                TimedParagraph {
+                    text: "today we look at tokenizers".to_string(),
+                    start: 0.0,
+                    end: 5.0,
+                    segment_range: 0..3,
+                },
+                TimedParagraph {
+                    text: "they split text into pieces".to_string(),
+                    start: 5.0,
+                    end: 9.0,
+                    segment_range: 3..5,
+                },
+            ]
+        );
+
+        let chunks = TimeAlignedSplitter::new(WordTokenizer::new(), 5)
+            .with_overlap(TimedOverlap::Seconds(2.0))
+            .split(&segments)
+            .unwrap();
+        let ranges: Vec<Range<usize>> = chunks.iter().map(|c| c.segment_range.clone()).collect();
+        assert_eq!(ranges, vec![0..3, 2..4, 3..5]);
+
+        let chunks = TimeAlignedSplitter::new(WordTokenizer::new(), 2)
+            .split(&segments[..1])
+            .unwrap();
+        assert_eq!(chunks[1].text, "look");
+        assert_eq!(chunks[1].segment_range, 0..1);
+        assert!((chunks[1].start - 1.38).abs() < 0.01);
+    }
+}
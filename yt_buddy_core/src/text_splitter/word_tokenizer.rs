@@ -0,0 +1,89 @@
+use llm_chain::tokens::{Tokenizer, TokenizerError};
+use llm_chain::TextSplitter;
+
+use super::sentence_segmenter::word_ranges;
+use super::ModelTokenizer;
+
+/// One token per whitespace separated word, the tokenizer of
+/// `HashingEmbeddings`.
+///
+/// Chunks are exact substrings of the split text, from their first to
+/// their last word.
+#[derive(Debug, Clone, Default)]
+pub struct WordTokenizer {
+    max_seq_length: Option<usize>,
+}
+
+impl WordTokenizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_seq_length(mut self, max_seq_length: usize) -> Self {
+        self.max_seq_length = Some(max_seq_length);
+        self
+    }
+}
+
+impl Tokenizer<String> for WordTokenizer {
+    fn tokenize_str(&self, doc: &str) -> Result<Vec<String>, TokenizerError> {
+        Ok(doc.split_whitespace().map(String::from).collect())
+    }
+
+    fn to_string(&self, tokens: Vec<String>) -> Result<String, TokenizerError> {
+        Ok(tokens.join(" "))
+    }
+}
+
+impl TextSplitter<String> for WordTokenizer {
+    fn split_text(
+        &self,
+        doc: &str,
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Result<Vec<String>, TokenizerError> {
+        let words = word_ranges(doc, 0..doc.len());
+        let max_tokens = max_tokens_per_chunk.max(1);
+        let step = max_tokens.saturating_sub(chunk_overlap).max(1);
+
+        let mut chunks = vec![];
+        let mut start = 0;
+        while start < words.len() {
+            let end = usize::min(start + max_tokens, words.len());
+            chunks.push(doc[words[start].start..words[end - 1].end].to_string());
+
+            if end == words.len() {
+                break;
+            }
+            start += step;
+        }
+
+        Ok(chunks)
+    }
+}
+
+impl ModelTokenizer for WordTokenizer {
+    type TokenType = String;
+
+    fn max_seq_length(&self) -> Option<usize> {
+        self.max_seq_length
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_text_test() {
+        let text = "  One two\tthree\nfour five six ";
+
+        let chunks = WordTokenizer::new().split_text(text, 4, 2).unwrap();
+        assert_eq!(chunks, vec!["One two\tthree\nfour", "three\nfour five six"]);
+        assert_eq!(WordTokenizer::new().count_tokens(text), 6);
+    }
+}